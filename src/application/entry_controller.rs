@@ -1,5 +1,5 @@
-use super::entry_controller_interface::EntryControllerInterface;
-use super::entry_persistence_interface::EntryPersistenceInterface;
+use super::entry_controller_interface::{EntryControllerInterface, ImportReport, ImportRow};
+use super::entry_persistence_interface::{BackupInfo, ChainReport, EntryPersistenceInterface};
 use domain;
 use std::rc::Rc;
 
@@ -19,16 +19,162 @@ impl EntryControllerInterface for EntryController {
     };
   }
   fn get_entries(&self) -> Result<Vec<domain::EntryObject>, String> {
-    let mut entries = self.entry_persistence.read_entries().unwrap();
+    return self
+      .entry_persistence
+      .query_entries(&domain::EntryFilter::new());
+  }
+  fn get_filtered_entries(
+    &self,
+    filter: &domain::EntryFilter,
+  ) -> Result<Vec<domain::EntryObject>, String> {
+    return self.entry_persistence.query_entries(filter);
+  }
+  // groups overlapping context windows, context entries come with empty spans
+  fn search_entries(
+    &self,
+    search: &domain::EntrySearch,
+    filter: &domain::EntryFilter,
+    before: usize,
+    after: usize,
+  ) -> Result<Vec<Vec<domain::SearchHit>>, String> {
+    let entries = self.get_filtered_entries(filter)?;
+    let mut groups: Vec<Vec<domain::SearchHit>> = vec![];
+    let mut last_index: Option<usize> = None;
+    for (index, entry) in entries.iter().enumerate() {
+      if search.find(entry).is_none() {
+        continue;
+      }
+      let start = index.saturating_sub(before);
+      let end = std::cmp::min(index + after, entries.len() - 1);
+      let first = match last_index {
+        Some(last) if end <= last => continue,
+        Some(last) if start <= last + 1 => last + 1,
+        _ => {
+          groups.push(vec![]);
+          start
+        }
+      };
+      let group = groups.last_mut().unwrap();
+      for item in &entries[first..=end] {
+        group.push(search.find(item).unwrap_or(domain::SearchHit {
+          entry: item.clone(),
+          tag_spans: vec![],
+          message_spans: vec![],
+        }));
+      }
+      last_index = Some(end);
+    }
+    return Ok(groups);
+  }
+  // the last entries written that match, the journal is only read until there are enough
+  fn get_latest_entries(
+    &self,
+    filter: &domain::EntryFilter,
+    count: usize,
+  ) -> Result<Vec<domain::EntryObject>, String> {
+    let mut entries: Vec<domain::EntryObject> = vec![];
+    if count > 0 {
+      self.entry_persistence.for_each_entry(true, &mut |entry| {
+        if filter.matches(&entry) {
+          entries.push(entry);
+        }
+        return entries.len() < count;
+      })?;
+    }
     entries.sort();
     return Ok(entries);
   }
+  fn get_stats(&self, filter: &domain::EntryFilter) -> Result<domain::EntryStats, String> {
+    let entries = self.get_filtered_entries(filter)?;
+    return Ok(domain::EntryStats::from_entries(&entries));
+  }
+  fn get_entry(&self, ins: &str) -> Result<domain::EntryObject, String> {
+    return self
+      .entry_persistence
+      .find_entry(ins)?
+      .ok_or_else(|| format!("No entry found with ins {}", ins));
+  }
   fn post_entry(&self, mut entry: domain::EntryObject) -> Result<domain::EntryObject, String> {
     let ins = chrono::Local::now().timestamp_millis().to_string();
     entry.metadata.ins = Some(ins);
-    let _validate = self.entry_business.validate(&entry).or_else(|err| {
-      return Err(err.message());
-    });
-    return self.entry_persistence.write_entry(entry);
+    self
+      .entry_business
+      .validate(&entry)
+      .map_err(|err| err.message())?;
+    // the timestamp is only a first choice, the journal may already hold it
+    return self.entry_persistence.insert_entry(entry);
+  }
+  // keeps the ins of every row, rejected rows don't stop the valid ones from being written
+  fn import_entries(&self, rows: Vec<ImportRow>, dry_run: bool) -> Result<ImportReport, String> {
+    let mut known_ins: Vec<String> = self
+      .get_entries()?
+      .into_iter()
+      .filter_map(|entry| entry.metadata.ins)
+      .collect();
+    let mut report = ImportReport {
+      imported: vec![],
+      rejected: vec![],
+    };
+    for row in rows {
+      let mut entry = match row.entry {
+        Ok(entry) => entry,
+        Err(err) => {
+          report.rejected.push((row.line, err));
+          continue;
+        }
+      };
+      // stored the way posting would store it, e.g. 13-Oct-20 as 13-oct-20
+      match self.entry_business.validate(&entry).and_then(|_ok| {
+        self
+          .entry_business
+          .validate_date(entry.metadata.date.as_str())
+      }) {
+        Ok(date) => entry.metadata.date = date,
+        Err(err) => {
+          report.rejected.push((row.line, err.message()));
+          continue;
+        }
+      }
+      let ins = entry.metadata.ins.clone().unwrap();
+      if known_ins.contains(&ins) {
+        report
+          .rejected
+          .push((row.line, format!("Entry with ins {} already exists", ins)));
+        continue;
+      }
+      known_ins.push(ins);
+      report.imported.push(entry);
+    }
+    if !dry_run && !report.imported.is_empty() {
+      self
+        .entry_persistence
+        .write_entries(report.imported.clone())?;
+    }
+    return Ok(report);
+  }
+  fn update_entry(&self, entry: domain::EntryObject) -> Result<domain::EntryObject, String> {
+    self
+      .entry_business
+      .validate(&entry)
+      .map_err(|err| err.message())?;
+    return self.entry_persistence.update_entry(entry);
+  }
+  fn delete_entries(&self, ins_list: &[String]) -> Result<Vec<domain::EntryObject>, String> {
+    return self.entry_persistence.delete_entries(ins_list);
+  }
+  fn compact(&self) -> Result<usize, String> {
+    return self.entry_persistence.compact();
+  }
+  fn list_backups(&self) -> Result<Vec<BackupInfo>, String> {
+    return self.entry_persistence.list_backups();
+  }
+  fn archive(&self) -> Result<Vec<String>, String> {
+    return self.entry_persistence.archive();
+  }
+  fn verify_chain(&self) -> Result<Vec<ChainReport>, String> {
+    return self.entry_persistence.verify_chain();
+  }
+  fn reseal_chain(&self) -> Result<usize, String> {
+    return self.entry_persistence.reseal_chain();
   }
 }
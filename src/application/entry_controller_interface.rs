@@ -1,12 +1,48 @@
-use super::entry_persistence_interface::EntryPersistenceInterface;
+use super::entry_persistence_interface::{BackupInfo, ChainReport, EntryPersistenceInterface};
 use domain;
 use std::rc::Rc;
 
+pub struct ImportRow {
+  pub line: usize,
+  pub entry: Result<domain::EntryObject, String>,
+}
+
+pub struct ImportReport {
+  pub imported: Vec<domain::EntryObject>,
+  pub rejected: Vec<(usize, String)>,
+}
+
 pub trait EntryControllerInterface {
   fn new(
     entry_business: Rc<dyn domain::EntryHandler>,
     entry_persistence: Rc<dyn EntryPersistenceInterface>,
   ) -> Self;
   fn get_entries(&self) -> Result<Vec<domain::EntryObject>, String>;
+  fn get_filtered_entries(
+    &self,
+    filter: &domain::EntryFilter,
+  ) -> Result<Vec<domain::EntryObject>, String>;
+  fn search_entries(
+    &self,
+    search: &domain::EntrySearch,
+    filter: &domain::EntryFilter,
+    before: usize,
+    after: usize,
+  ) -> Result<Vec<Vec<domain::SearchHit>>, String>;
+  fn get_latest_entries(
+    &self,
+    filter: &domain::EntryFilter,
+    count: usize,
+  ) -> Result<Vec<domain::EntryObject>, String>;
+  fn get_stats(&self, filter: &domain::EntryFilter) -> Result<domain::EntryStats, String>;
+  fn get_entry(&self, ins: &str) -> Result<domain::EntryObject, String>;
   fn post_entry(&self, entry: domain::EntryObject) -> Result<domain::EntryObject, String>;
+  fn import_entries(&self, rows: Vec<ImportRow>, dry_run: bool) -> Result<ImportReport, String>;
+  fn update_entry(&self, entry: domain::EntryObject) -> Result<domain::EntryObject, String>;
+  fn delete_entries(&self, ins_list: &[String]) -> Result<Vec<domain::EntryObject>, String>;
+  fn compact(&self) -> Result<usize, String>;
+  fn list_backups(&self) -> Result<Vec<BackupInfo>, String>;
+  fn archive(&self) -> Result<Vec<String>, String>;
+  fn verify_chain(&self) -> Result<Vec<ChainReport>, String>;
+  fn reseal_chain(&self) -> Result<usize, String>;
 }
@@ -1,7 +1,146 @@
 use domain;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoadMode {
+  Strict,
+  Lenient,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadDiagnostic {
+  pub line: usize,
+  pub raw: String,
+  pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupInfo {
+  pub path: String,
+  pub created: chrono::NaiveDateTime,
+  pub entries: usize,
+  pub size: u64,
+}
+
+// one journal file, verified up to its first broken link
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainReport {
+  pub path: String,
+  pub lines: usize,
+  pub last_hash: Option<String>,
+  pub first_broken: Option<LoadDiagnostic>,
+}
+
+// the ins right after this one, with as many digits
+pub fn next_ins(ins: &str) -> Result<String, String> {
+  let number = ins
+    .parse::<u64>()
+    .map_err(|err| format!("Invalid ins {}: {}", ins, err))?;
+  return Ok(format!("{:0width$}", number + 1, width = ins.len()));
+}
+
+// keeps the ins if it comes after the last one stored, the one after the last otherwise
+pub fn ins_past(ins: String, last: Option<String>) -> Result<String, String> {
+  let last = match last {
+    Some(last) => last,
+    None => return Ok(ins),
+  };
+  let number = ins
+    .parse::<u64>()
+    .map_err(|err| format!("Invalid ins {}: {}", ins, err))?;
+  match last.parse::<u64>() {
+    Ok(last_number) if last_number >= number => return next_ins(last.as_str()),
+    _ => return Ok(ins),
+  }
+}
+
 pub trait EntryPersistenceInterface {
   fn read_entries(&self) -> Result<Vec<domain::EntryObject>, String>;
+  // matching entries in EntryObject order, backends that can filter and sort natively override it
+  fn query_entries(
+    &self,
+    filter: &domain::EntryFilter,
+  ) -> Result<Vec<domain::EntryObject>, String> {
+    let mut entries: Vec<domain::EntryObject> = self
+      .read_entries()?
+      .into_iter()
+      .filter(|entry| filter.matches(entry))
+      .collect();
+    entries.sort();
+    return Ok(entries);
+  }
+  // the first entry with this ins, backends with an index override it
+  fn find_entry(&self, ins: &str) -> Result<Option<domain::EntryObject>, String> {
+    let mut found: Option<domain::EntryObject> = None;
+    self.for_each_entry(false, &mut |entry| {
+      if entry.metadata.ins.as_deref() == Some(ins) {
+        found = Some(entry);
+        return false;
+      }
+      return true;
+    })?;
+    return Ok(found);
+  }
+  // entries in storage order, last stored first when reversed, stops as soon as visit returns false
+  fn for_each_entry(
+    &self,
+    reverse: bool,
+    visit: &mut dyn FnMut(domain::EntryObject) -> bool,
+  ) -> Result<(), String> {
+    let mut entries = self.read_entries()?;
+    if reverse {
+      entries.reverse();
+    }
+    for entry in entries {
+      if !visit(entry) {
+        break;
+      }
+    }
+    return Ok(());
+  }
   fn write_entry(&self, entry: domain::EntryObject) -> Result<domain::EntryObject, String>;
+  // writes a posted entry under a free ins, past the last one stored, backends that several
+  // processes write to pick it while the others are locked out
+  fn insert_entry(&self, mut entry: domain::EntryObject) -> Result<domain::EntryObject, String> {
+    let mut last: Option<String> = None;
+    self.for_each_entry(true, &mut |stored| {
+      last = stored.metadata.ins;
+      return false;
+    })?;
+    let mut ins = ins_past(entry.metadata.ins.unwrap_or_default(), last)?;
+    while self.find_entry(ins.as_str())?.is_some() {
+      ins = next_ins(ins.as_str())?;
+    }
+    entry.metadata.ins = Some(ins);
+    return self.write_entry(entry);
+  }
+  fn write_entries(
+    &self,
+    entries: Vec<domain::EntryObject>,
+  ) -> Result<Vec<domain::EntryObject>, String>;
+  fn update_entry(&self, entry: domain::EntryObject) -> Result<domain::EntryObject, String>;
+  fn delete_entries(&self, ins_list: &[String]) -> Result<Vec<domain::EntryObject>, String>;
+  fn compact(&self) -> Result<usize, String>;
+  // lines skipped by lenient reads since the last call
+  fn take_diagnostics(&self) -> Vec<LoadDiagnostic>;
+  // repairs made while the command ran, for backends that only check their files once opened
+  fn take_recovery(&self) -> Vec<String> {
+    return vec![];
+  }
+  // newest first
+  fn list_backups(&self) -> Result<Vec<BackupInfo>, String> {
+    return Err("This backend doesn't keep backups".to_string());
+  }
+  // gzips what is no longer written to, returns the archived files
+  fn archive(&self) -> Result<Vec<String>, String> {
+    return Err("This backend doesn't archive files".to_string());
+  }
+  fn verify_chain(&self) -> Result<Vec<ChainReport>, String> {
+    return Err("This backend doesn't keep a hash chain".to_string());
+  }
+  // seals every line as it is now, returns the number of lines sealed
+  fn reseal_chain(&self) -> Result<usize, String> {
+    return Err("This backend doesn't keep a hash chain".to_string());
+  }
   // fn upsert_entry_file_meta(&self, file_meta: String) -> Result<String, String>;
 }
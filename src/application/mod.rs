@@ -5,4 +5,3 @@ mod entry_persistence_interface;
 pub use self::entry_controller::*;
 pub use self::entry_controller_interface::*;
 pub use self::entry_persistence_interface::*;
-
@@ -0,0 +1,52 @@
+use super::entry_object::*;
+
+use chrono::NaiveDate;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryFilter {
+  pub date_from: Option<NaiveDate>,
+  pub date_to: Option<NaiveDate>,
+  pub tag: Option<String>,
+  pub time: Option<String>,
+  pub message: Option<String>,
+}
+
+impl EntryFilter {
+  pub fn new() -> EntryFilter {
+    return EntryFilter::default();
+  }
+  pub fn matches(&self, entry: &EntryObject) -> bool {
+    if self.date_from.is_some() || self.date_to.is_some() {
+      let date = match NaiveDate::parse_from_str(entry.metadata.date.as_str(), "%d-%b-%y") {
+        Ok(value) => value,
+        Err(_err) => return false,
+      };
+      if let Some(from) = self.date_from {
+        if date < from {
+          return false;
+        }
+      }
+      if let Some(to) = self.date_to {
+        if date > to {
+          return false;
+        }
+      }
+    }
+    if let Some(tag) = &self.tag {
+      if &entry.metadata.tag != tag {
+        return false;
+      }
+    }
+    if let Some(time) = &self.time {
+      if &entry.metadata.time != time {
+        return false;
+      }
+    }
+    if let Some(message) = &self.message {
+      if !entry.message.contains(message.as_str()) {
+        return false;
+      }
+    }
+    return true;
+  }
+}
@@ -3,7 +3,7 @@ use std::clone::Clone;
 use std::cmp::Ordering;
 use std::fmt::Debug;
 
-pub static TIME_VEC: &'static [&str] = &["latenight", "morning", "afternoon", "night", "n/a"];
+pub static TIME_VEC: &[&str] = &["latenight", "morning", "afternoon", "night", "n/a"];
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EntryMetadata {
@@ -0,0 +1,64 @@
+use super::entry_object::*;
+use super::error::*;
+
+use regex::{Regex, RegexBuilder};
+
+pub struct EntrySearch {
+  regex: Regex,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+  pub entry: EntryObject,
+  pub tag_spans: Vec<(usize, usize)>,
+  pub message_spans: Vec<(usize, usize)>,
+}
+
+impl EntrySearch {
+  pub fn new(pattern: &str, regex: bool, ignore_case: bool) -> Result<EntrySearch, DomainError> {
+    if pattern.is_empty() {
+      return Err(DomainError::new(
+        DomainErrorCode::InvalidFormat,
+        "Search pattern must not be empty".to_string(),
+      ));
+    }
+    let source = if regex {
+      pattern.to_string()
+    } else {
+      regex::escape(pattern)
+    };
+    match RegexBuilder::new(source.as_str())
+      .case_insensitive(ignore_case)
+      .build()
+    {
+      Ok(regex) => return Ok(EntrySearch { regex }),
+      Err(err) => {
+        return Err(DomainError::new(
+          DomainErrorCode::InvalidFormat,
+          format!("Invalid search pattern: {}", err),
+        ))
+      }
+    }
+  }
+  fn spans(&self, text: &str) -> Vec<(usize, usize)> {
+    return self
+      .regex
+      .find_iter(text)
+      .filter(|found| found.start() != found.end())
+      .map(|found| (found.start(), found.end()))
+      .collect();
+  }
+  // an entry hits when its tag or its message matches, spans are byte ranges
+  pub fn find(&self, entry: &EntryObject) -> Option<SearchHit> {
+    let tag_spans = self.spans(entry.metadata.tag.as_str());
+    let message_spans = self.spans(entry.message.as_str());
+    if tag_spans.is_empty() && message_spans.is_empty() {
+      return None;
+    }
+    return Some(SearchHit {
+      entry: entry.clone(),
+      tag_spans,
+      message_spans,
+    });
+  }
+}
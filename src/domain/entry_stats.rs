@@ -0,0 +1,97 @@
+use super::entry_object::*;
+
+use chrono::prelude::*;
+use std::collections::BTreeMap;
+
+static WEEKDAY_VEC: &[&str] = &["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatsBucket {
+  pub name: String,
+  pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntryStats {
+  pub total: usize,
+  pub per_tag: Vec<StatsBucket>,
+  pub per_month: Vec<StatsBucket>,
+  pub per_weekday: Vec<StatsBucket>,
+  pub per_time: Vec<StatsBucket>,
+  pub first_date: Option<String>,
+  pub last_date: Option<String>,
+  pub average_per_day: f64,
+}
+
+impl EntryStats {
+  fn buckets(counts: BTreeMap<String, usize>) -> Vec<StatsBucket> {
+    return counts
+      .into_iter()
+      .map(|(name, count)| StatsBucket { name, count })
+      .collect();
+  }
+  // entries with an unparseable date only count towards total, tag and time
+  pub fn from_entries(entries: &[EntryObject]) -> EntryStats {
+    let mut per_tag: BTreeMap<String, usize> = BTreeMap::new();
+    let mut per_month: BTreeMap<String, usize> = BTreeMap::new();
+    let mut per_weekday: Vec<usize> = vec![0; WEEKDAY_VEC.len()];
+    let mut per_time: Vec<usize> = vec![0; TIME_VEC.len()];
+    let mut first: Option<NaiveDate> = None;
+    let mut last: Option<NaiveDate> = None;
+    for entry in entries {
+      *per_tag.entry(entry.metadata.tag.clone()).or_insert(0) += 1;
+      if let Some(index) = TIME_VEC
+        .iter()
+        .position(|&time_str| time_str == entry.metadata.time)
+      {
+        per_time[index] += 1;
+      }
+      let date = match NaiveDate::parse_from_str(entry.metadata.date.as_str(), "%d-%b-%y") {
+        Ok(value) => value,
+        Err(_err) => continue,
+      };
+      *per_month
+        .entry(date.format("%Y-%m").to_string())
+        .or_insert(0) += 1;
+      per_weekday[date.weekday().num_days_from_monday() as usize] += 1;
+      if first.is_none_or(|first_date| date < first_date) {
+        first = Some(date);
+      }
+      if last.is_none_or(|last_date| date > last_date) {
+        last = Some(date);
+      }
+    }
+    let average_per_day = match (first, last) {
+      (Some(first_date), Some(last_date)) => {
+        let days = (last_date - first_date).num_days() + 1;
+        entries.len() as f64 / days as f64
+      }
+      _ => 0.0,
+    };
+    let format_date = |date: NaiveDate| date.format("%d-%b-%y").to_string().to_lowercase();
+    return EntryStats {
+      total: entries.len(),
+      per_tag: Self::buckets(per_tag),
+      per_month: Self::buckets(per_month),
+      per_weekday: WEEKDAY_VEC
+        .iter()
+        .zip(per_weekday)
+        .map(|(name, count)| StatsBucket {
+          name: name.to_string(),
+          count,
+        })
+        .collect(),
+      per_time: TIME_VEC
+        .iter()
+        .zip(per_time)
+        .map(|(name, count)| StatsBucket {
+          name: name.to_string(),
+          count,
+        })
+        .collect(),
+      first_date: first.map(format_date),
+      last_date: last.map(format_date),
+      average_per_day,
+    };
+  }
+}
@@ -1,10 +1,11 @@
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DomainErrorCode {
   InvalidFormat,
   MaxLengthExceeded,
   MissingIns,
 }
 
+#[derive(Debug)]
 pub struct DomainError {
   code: DomainErrorCode,
   message: String,
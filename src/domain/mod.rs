@@ -1,9 +1,15 @@
 mod entry_business;
+mod entry_filter;
 mod entry_object;
+mod entry_search;
+mod entry_stats;
 mod error;
 
 pub use self::entry_business::*;
+pub use self::entry_filter::*;
 pub use self::entry_object::*;
+pub use self::entry_search::*;
+pub use self::entry_stats::*;
 pub use self::error::*;
 
 /* -----------------------------------TESTS------------------------------------------ */
@@ -25,6 +31,15 @@ pub use self::error::*;
 - 32 characters max, characters allowed: alphanumerical, + - = . , : _ \ / ( ) < > $"
 ## EntryObject sorting
 - date -> time -> ins -> tag -> message
+## EntryObject filtering
+- date range is inclusive on both ends
+- tag and time must match exactly, message matches by substring
+## EntryObject search
+- matches tag and message, as plain text or regex, optionally ignoring case
+- reports the matched byte spans of tag and message
+## EntryObject statistics
+- counts per tag, per month, per weekday and per time
+- average per day over the days between first and last entry, both included
 */
 
 #[cfg(test)]
@@ -38,59 +53,59 @@ mod tests {
   fn validate_tag_test() {
     let entry_validator = EntryBusiness {};
     let invalid_characters = entry_validator.validate_tag("aa..");
-    assert_eq!(invalid_characters.is_err(), true);
+    assert!(invalid_characters.is_err());
     let too_long = entry_validator.validate_tag("aaaaaaaaaaaaa");
-    assert_eq!(too_long.is_err(), true);
+    assert!(too_long.is_err());
     let uppercase = entry_validator.validate_tag("aaAa");
-    assert_eq!(uppercase.is_err(), true);
+    assert!(uppercase.is_err());
     let ok = entry_validator.validate_tag("aaa");
-    assert_eq!(ok.is_ok(), true);
+    assert!(ok.is_ok());
   }
   #[test]
   fn validate_date_test() {
     let entry_validator = EntryBusiness {};
     let wrong_format = entry_validator.validate_date("....");
-    assert_eq!(wrong_format.is_err(), true);
+    assert!(wrong_format.is_err());
     let ok = entry_validator.validate_date("10-dec-20");
-    assert_eq!(ok.is_ok(), true);
+    assert!(ok.is_ok());
   }
   #[test]
   fn validate_time_test() {
     let entry_validator = EntryBusiness {};
     let wrong_format = entry_validator.validate_date("....");
-    assert_eq!(wrong_format.is_err(), true);
+    assert!(wrong_format.is_err());
     let morning = entry_validator.validate_time("morning");
-    assert_eq!(morning.is_ok(), true);
+    assert!(morning.is_ok());
     let afternoon = entry_validator.validate_time("afternoon");
-    assert_eq!(afternoon.is_ok(), true);
+    assert!(afternoon.is_ok());
     let night = entry_validator.validate_time("night");
-    assert_eq!(night.is_ok(), true);
+    assert!(night.is_ok());
     let latenight = entry_validator.validate_time("latenight");
-    assert_eq!(latenight.is_ok(), true);
+    assert!(latenight.is_ok());
     let notapplicable = entry_validator.validate_time("n/a");
-    assert_eq!(notapplicable.is_ok(), true);
+    assert!(notapplicable.is_ok());
   }
   #[test]
   fn validate_ins_test() {
     let entry_validator = EntryBusiness {};
     let wrong_length = entry_validator.validate_ins("111111111111");
     let wrong_format = entry_validator.validate_ins("111111111111a");
-    assert_eq!(wrong_length.is_err(), true);
-    assert_eq!(wrong_format.is_err(), true);
+    assert!(wrong_length.is_err());
+    assert!(wrong_format.is_err());
     let allowed = entry_validator.validate_ins("1111111111111");
-    assert_eq!(allowed.is_ok(), true);
+    assert!(allowed.is_ok());
   }
   #[test]
   fn validate_message_test() {
     let entry_validator = EntryBusiness {};
     let too_long = entry_validator.validate_message("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa");
-    assert_eq!(too_long.is_err(), true);
+    assert!(too_long.is_err());
     let wrong_format = entry_validator.validate_message("[]ç");
-    assert_eq!(wrong_format.is_err(), true);
+    assert!(wrong_format.is_err());
     let allowed = entry_validator.validate_message("A-z0-9 .,:$()<>-=\\/_");
-    assert_eq!(allowed.is_ok(), true);
+    assert!(allowed.is_ok());
     let allowed2 = entry_validator.validate_message("d");
-    assert_eq!(allowed2.is_ok(), true);
+    assert!(allowed2.is_ok());
   }
   /* SORTING */
   #[test]
@@ -360,4 +375,133 @@ mod tests {
     */
     assert_eq!(obj_vec_ordered, obj_vec_unordered);
   }
+  /* FILTERING */
+  #[test]
+  fn filter_entry_test() {
+    fn gen_filter_entry(date: &str, time: &str, tag: &str, message: &str) -> EntryObject {
+      let metadata = EntryMetadata {
+        ins: Some("1111111111111".to_string()),
+        date: date.to_string(),
+        time: time.to_string(),
+        tag: tag.to_string(),
+      };
+      return EntryObject {
+        metadata,
+        message: message.to_string(),
+      };
+    }
+    let entry = gen_filter_entry("13-oct-20", "morning", "fit", "aerobic (5/5)");
+    assert!(EntryFilter::new().matches(&entry));
+
+    let mut range = EntryFilter::new();
+    range.date_from = Some(NaiveDate::from_ymd(2020, 10, 13));
+    range.date_to = Some(NaiveDate::from_ymd(2020, 10, 13));
+    assert!(range.matches(&entry));
+    range.date_from = Some(NaiveDate::from_ymd(2020, 10, 14));
+    range.date_to = None;
+    assert!(!range.matches(&entry));
+    range.date_from = None;
+    range.date_to = Some(NaiveDate::from_ymd(2020, 10, 12));
+    assert!(!range.matches(&entry));
+
+    let mut tag = EntryFilter::new();
+    tag.tag = Some("fit".to_string());
+    assert!(tag.matches(&entry));
+    tag.tag = Some("fi".to_string());
+    assert!(!tag.matches(&entry));
+
+    let mut time = EntryFilter::new();
+    time.time = Some("night".to_string());
+    assert!(!time.matches(&entry));
+
+    let mut message = EntryFilter::new();
+    message.message = Some("bic (5".to_string());
+    assert!(message.matches(&entry));
+    message.message = Some("Aerobic".to_string());
+    assert!(!message.matches(&entry));
+  }
+  /* SEARCH */
+  #[test]
+  fn search_entry_test() {
+    let metadata = EntryMetadata {
+      ins: Some("1111111111111".to_string()),
+      date: "13-oct-20".to_string(),
+      time: "morning".to_string(),
+      tag: "fit".to_string(),
+    };
+    let entry = EntryObject {
+      metadata,
+      message: "Fit aerobic (5/5) fit".to_string(),
+    };
+    let substring = EntrySearch::new("fit", false, false).unwrap();
+    let hit = substring.find(&entry).unwrap();
+    assert_eq!(hit.tag_spans, vec![(0, 3)]);
+    assert_eq!(hit.message_spans, vec![(18, 21)]);
+
+    let ignore_case = EntrySearch::new("FIT", false, true).unwrap();
+    let hit = ignore_case.find(&entry).unwrap();
+    assert_eq!(hit.message_spans, vec![(0, 3), (18, 21)]);
+
+    let literal = EntrySearch::new("(5/5)", false, false).unwrap();
+    assert_eq!(literal.find(&entry).unwrap().message_spans, vec![(12, 17)]);
+    assert_eq!(literal.find(&entry).unwrap().tag_spans, vec![]);
+
+    let regex = EntrySearch::new(r"\(\d/\d\)", true, false).unwrap();
+    assert_eq!(regex.find(&entry).unwrap().message_spans, vec![(12, 17)]);
+
+    let miss = EntrySearch::new("run", false, false).unwrap();
+    assert!(miss.find(&entry).is_none());
+
+    assert!(EntrySearch::new("(", true, false).is_err());
+    assert!(EntrySearch::new("", false, false).is_err());
+  }
+  /* STATISTICS */
+  #[test]
+  fn stats_entry_test() {
+    fn gen_stats_entry(date: &str, time: &str, tag: &str) -> EntryObject {
+      let metadata = EntryMetadata {
+        ins: Some("1111111111111".to_string()),
+        date: date.to_string(),
+        time: time.to_string(),
+        tag: tag.to_string(),
+      };
+      return EntryObject {
+        metadata,
+        message: "hello".to_string(),
+      };
+    }
+    let entries = vec![
+      gen_stats_entry("28-feb-24", "morning", "fit"),
+      gen_stats_entry("29-feb-24", "night", "fit"),
+      gen_stats_entry("02-mar-24", "night", "work"),
+      gen_stats_entry("08-mar-24", "n/a", "fit"),
+    ];
+    let stats = EntryStats::from_entries(&entries);
+    assert_eq!(stats.total, 4);
+    let count = |buckets: &Vec<StatsBucket>, name: &str| -> usize {
+      return buckets
+        .iter()
+        .find(|bucket| bucket.name == name)
+        .map(|bucket| bucket.count)
+        .unwrap();
+    };
+    assert_eq!(count(&stats.per_tag, "fit"), 3);
+    assert_eq!(count(&stats.per_tag, "work"), 1);
+    assert_eq!(count(&stats.per_month, "2024-02"), 2);
+    assert_eq!(count(&stats.per_month, "2024-03"), 2);
+    assert_eq!(count(&stats.per_weekday, "wed"), 1);
+    assert_eq!(count(&stats.per_weekday, "thu"), 1);
+    assert_eq!(count(&stats.per_weekday, "fri"), 1);
+    assert_eq!(count(&stats.per_weekday, "sat"), 1);
+    assert_eq!(count(&stats.per_time, "night"), 2);
+    assert_eq!(count(&stats.per_time, "latenight"), 0);
+    assert_eq!(stats.first_date, Some("28-feb-24".to_string()));
+    assert_eq!(stats.last_date, Some("08-mar-24".to_string()));
+    assert_eq!(stats.average_per_day, 0.4);
+
+    let empty = EntryStats::from_entries(&[]);
+    assert_eq!(empty.total, 0);
+    assert_eq!(empty.first_date, None);
+    assert_eq!(empty.average_per_day, 0.0);
+  }
 }
@@ -0,0 +1,370 @@
+use std::env;
+use std::path::PathBuf;
+
+use clap::ArgMatches;
+
+use application::LoadMode;
+
+use super::encryption::PassphraseSource;
+use super::persistence_sharded::ShardPeriod;
+use super::persistence_textfile::FileSystemConfiguration;
+
+static BACKEND_VEC: &[&str] = &["textfile", "jsonl", "sqlite", "memory"];
+
+// every setting is optional so the same shape works for the file, env vars and flags
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigurationLayer {
+  backend: Option<String>,
+  default_tag: Option<String>,
+  file_path: Option<String>,
+  file_name: Option<String>,
+  file_current_extension: Option<String>,
+  file_backup_extension: Option<String>,
+  lock_timeout_ms: Option<u64>,
+  load_mode: Option<LoadMode>,
+  format_version: Option<u8>,
+  backup_count: Option<usize>,
+  backup_max_age_days: Option<u64>,
+  shard_by: Option<ShardPeriod>,
+  hash_chain: Option<bool>,
+  encryption: Option<PassphraseSource>,
+  encryption_key_file: Option<String>,
+}
+
+impl ConfigurationLayer {
+  fn from_env() -> Result<ConfigurationLayer, String> {
+    return ConfigurationLayer::from_vars(&|name| env::var(name).ok());
+  }
+  // a variable that is set but can't be used is an error rather than silently ignored
+  fn from_vars(var: &dyn Fn(&str) -> Option<String>) -> Result<ConfigurationLayer, String> {
+    let lock_timeout_ms = match var("ENTRYLOGGER_LOCK_TIMEOUT_MS") {
+      Some(value) => Some(
+        value
+          .parse::<u64>()
+          .map_err(|err| format!("Invalid ENTRYLOGGER_LOCK_TIMEOUT_MS {}: {}", value, err))?,
+      ),
+      None => None,
+    };
+    return Ok(ConfigurationLayer {
+      backend: var("ENTRYLOGGER_BACKEND"),
+      default_tag: var("ENTRYLOGGER_DEFAULT_TAG"),
+      file_path: var("ENTRYLOGGER_FILE_PATH"),
+      file_name: var("ENTRYLOGGER_FILE_NAME"),
+      lock_timeout_ms,
+      load_mode: match var("ENTRYLOGGER_LOAD_MODE").as_deref() {
+        Some("strict") => Some(LoadMode::Strict),
+        Some("lenient") => Some(LoadMode::Lenient),
+        Some(value) => {
+          return Err(format!(
+            "Invalid ENTRYLOGGER_LOAD_MODE {}, expected strict or lenient",
+            value
+          ))
+        }
+        None => None,
+      },
+      ..ConfigurationLayer::default()
+    });
+  }
+  fn from_matches(matches: &ArgMatches) -> ConfigurationLayer {
+    let value = |name: &str| matches.value_of(name).map(|value| value.to_string());
+    return ConfigurationLayer {
+      backend: value("backend"),
+      default_tag: value("default-tag"),
+      file_path: value("file-path"),
+      file_name: value("file-name"),
+      load_mode: if matches.is_present("lenient") {
+        Some(LoadMode::Lenient)
+      } else {
+        None
+      },
+      ..ConfigurationLayer::default()
+    };
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Configuration {
+  pub backend: String,
+  pub default_tag: Option<String>,
+  pub file_system: FileSystemConfiguration,
+  pub encryption: Option<PassphraseSource>,
+  pub encryption_key_file: Option<String>,
+  pub source: Option<PathBuf>,
+}
+
+impl Default for Configuration {
+  fn default() -> Configuration {
+    return Configuration {
+      backend: "textfile".to_owned(),
+      default_tag: None,
+      file_system: FileSystemConfiguration::default(),
+      encryption: None,
+      encryption_key_file: None,
+      source: None,
+    };
+  }
+}
+
+impl Configuration {
+  // --config, then $ENTRYLOGGER_CONFIG, then the user configuration directory if the file exists
+  fn file_location(matches: &ArgMatches) -> Option<(PathBuf, bool)> {
+    if let Some(path) = matches.value_of("config") {
+      return Some((PathBuf::from(path), true));
+    }
+    if let Ok(path) = env::var("ENTRYLOGGER_CONFIG") {
+      return Some((PathBuf::from(path), true));
+    }
+    let config_dir = match env::var("XDG_CONFIG_HOME") {
+      Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+      _ => PathBuf::from(env::var("HOME").ok()?).join(".config"),
+    };
+    return Some((config_dir.join("entrylogger").join("config.toml"), false));
+  }
+  fn expand_home(path: String) -> String {
+    if let Some(rest) = path.strip_prefix("~/") {
+      if let Ok(home) = env::var("HOME") {
+        return format!("{}/{}", home.trim_end_matches('/'), rest);
+      }
+    }
+    return path;
+  }
+  fn apply(&mut self, layer: ConfigurationLayer) {
+    if let Some(backend) = layer.backend {
+      self.backend = backend;
+    }
+    if let Some(default_tag) = layer.default_tag {
+      self.default_tag = Some(default_tag);
+    }
+    if let Some(file_path) = layer.file_path {
+      self.file_system.file_path = Self::expand_home(file_path);
+    }
+    if let Some(file_name) = layer.file_name {
+      self.file_system.file_name = file_name;
+    }
+    if let Some(file_current_extension) = layer.file_current_extension {
+      self.file_system.file_current_extension = file_current_extension;
+    }
+    if let Some(file_backup_extension) = layer.file_backup_extension {
+      self.file_system.file_backup_extension = file_backup_extension;
+    }
+    if let Some(lock_timeout_ms) = layer.lock_timeout_ms {
+      self.file_system.lock_timeout_ms = lock_timeout_ms;
+    }
+    if let Some(load_mode) = layer.load_mode {
+      self.file_system.load_mode = load_mode;
+    }
+    if let Some(format_version) = layer.format_version {
+      self.file_system.format_version = format_version;
+    }
+    if let Some(backup_count) = layer.backup_count {
+      self.file_system.backup_count = backup_count;
+    }
+    if let Some(backup_max_age_days) = layer.backup_max_age_days {
+      self.file_system.backup_max_age_days = Some(backup_max_age_days);
+    }
+    if let Some(shard_by) = layer.shard_by {
+      self.file_system.shard_by = Some(shard_by);
+    }
+    if let Some(hash_chain) = layer.hash_chain {
+      self.file_system.hash_chain = hash_chain;
+    }
+    if let Some(encryption) = layer.encryption {
+      self.encryption = Some(encryption);
+    }
+    if let Some(encryption_key_file) = layer.encryption_key_file {
+      self.encryption_key_file = Some(Self::expand_home(encryption_key_file));
+    }
+  }
+  // defaults < configuration file < environment variables < command-line flags
+  pub fn load(matches: &ArgMatches) -> Result<Configuration, String> {
+    let mut configuration = Configuration::default();
+    let mut layers: Vec<ConfigurationLayer> = vec![];
+    if let Some((path, required)) = Self::file_location(matches) {
+      if path.exists() || required {
+        let content = std::fs::read_to_string(&path)
+          .map_err(|err| format!("Couldn't read configuration {}: {}", path.display(), err))?;
+        let layer: ConfigurationLayer = toml::from_str(content.as_str())
+          .map_err(|err| format!("Invalid configuration {}: {}", path.display(), err))?;
+        layers.push(layer);
+        configuration.source = Some(path);
+      }
+    }
+    layers.push(ConfigurationLayer::from_env()?);
+    layers.push(ConfigurationLayer::from_matches(matches));
+    let extension_set = layers
+      .iter()
+      .any(|layer| layer.file_current_extension.is_some());
+    for layer in layers {
+      configuration.apply(layer);
+    }
+    // backends can't share a file, each gets its own extension unless told otherwise
+    if !extension_set {
+      match configuration.backend.as_str() {
+        "jsonl" => configuration.file_system.file_current_extension = ".jsonl".to_owned(),
+        "sqlite" => configuration.file_system.file_current_extension = ".db".to_owned(),
+        _ => {}
+      }
+    }
+    if !BACKEND_VEC.contains(&configuration.backend.as_str()) {
+      return Err(format!(
+        "Unknown backend {}, expected one of the following: {}",
+        configuration.backend,
+        BACKEND_VEC.join(", ")
+      ));
+    }
+    if ![1, 2].contains(&configuration.file_system.format_version) {
+      return Err(format!(
+        "Unsupported format_version {}, expected 1 or 2",
+        configuration.file_system.format_version
+      ));
+    }
+    let file_backend = ["textfile", "jsonl"].contains(&configuration.backend.as_str());
+    if configuration.file_system.shard_by.is_some() && !file_backend {
+      return Err("shard_by only applies to the textfile and jsonl backends".to_string());
+    }
+    // a sealed jsonl line wouldn't be plain JSON anymore
+    if configuration.file_system.hash_chain && configuration.backend != "textfile" {
+      return Err("hash_chain only applies to the textfile backend".to_string());
+    }
+    if configuration.encryption.is_some() && !file_backend {
+      return Err("encryption only applies to the textfile and jsonl backends".to_string());
+    }
+    if configuration.encryption == Some(PassphraseSource::Keyfile)
+      && configuration.encryption_key_file.is_none()
+    {
+      return Err("encryption = \"keyfile\" needs encryption_key_file".to_string());
+    }
+    if configuration.file_system.backup_count == 0 {
+      return Err("backup_count must keep at least one backup".to_string());
+    }
+    return Ok(configuration);
+  }
+  pub fn show(&self) -> Result<String, String> {
+    let layer = ConfigurationLayer {
+      backend: Some(self.backend.clone()),
+      default_tag: self.default_tag.clone(),
+      file_path: Some(self.file_system.file_path.clone()),
+      file_name: Some(self.file_system.file_name.clone()),
+      file_current_extension: Some(self.file_system.file_current_extension.clone()),
+      file_backup_extension: Some(self.file_system.file_backup_extension.clone()),
+      lock_timeout_ms: Some(self.file_system.lock_timeout_ms),
+      load_mode: Some(self.file_system.load_mode),
+      format_version: Some(self.file_system.format_version),
+      backup_count: Some(self.file_system.backup_count),
+      backup_max_age_days: self.file_system.backup_max_age_days,
+      shard_by: self.file_system.shard_by,
+      hash_chain: Some(self.file_system.hash_chain),
+      encryption: self.encryption,
+      encryption_key_file: self.encryption_key_file.clone(),
+    };
+    let source = match &self.source {
+      Some(path) => path.display().to_string(),
+      None => "none".to_string(),
+    };
+    let content = toml::to_string(&layer).map_err(|err| err.to_string())?;
+    return Ok(format!(
+      "# configuration file: {}\n{}",
+      source,
+      content.trim_end()
+    ));
+  }
+}
+
+/* -----------------------------------TESTS------------------------------------------ */
+#[cfg(test)]
+mod tests {
+  use super::super::interface_cli::CliInput;
+  use super::*;
+  #[test]
+  fn load_configuration_test() {
+    let dir = std::env::temp_dir().join(format!("entrylogger-config-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    std::fs::write(
+      &path,
+      "default_tag = \"work\"\nfile_path = \"/tmp/journal\"\nfile_backup_extension = \".old\"\n",
+    )
+    .unwrap();
+    let matches = CliInput::app().get_matches_from(vec![
+      "entrylogger",
+      "--config",
+      path.to_str().unwrap(),
+      "--file-path",
+      "/tmp/other",
+      "list",
+    ]);
+    let configuration = Configuration::load(&matches).unwrap();
+    assert_eq!(configuration.default_tag, Some("work".to_string()));
+    assert_eq!(configuration.file_system.file_path, "/tmp/other");
+    assert_eq!(configuration.file_system.file_name, "entries");
+    assert_eq!(configuration.file_system.file_backup_extension, ".old");
+    assert_eq!(configuration.source, Some(path.clone()));
+
+    std::fs::write(&path, "backend = \"jsonl\"\n").unwrap();
+    let configuration = Configuration::load(&matches).unwrap();
+    assert_eq!(configuration.file_system.file_current_extension, ".jsonl");
+    std::fs::write(
+      &path,
+      "backend = \"jsonl\"\nfile_current_extension = \".json\"\n",
+    )
+    .unwrap();
+    let configuration = Configuration::load(&matches).unwrap();
+    assert_eq!(configuration.file_system.file_current_extension, ".json");
+    std::fs::write(&path, "backend = \"jsonl\"\nhash_chain = true\n").unwrap();
+    assert_eq!(
+      Configuration::load(&matches).map(|_configuration| ()),
+      Err("hash_chain only applies to the textfile backend".to_string())
+    );
+
+    std::fs::write(&path, "backend = \"memory\"\nshard_by = \"month\"\n").unwrap();
+    assert_eq!(
+      Configuration::load(&matches).map(|_configuration| ()),
+      Err("shard_by only applies to the textfile and jsonl backends".to_string())
+    );
+    std::fs::write(&path, "backend = \"memory\"\n").unwrap();
+    assert_eq!(Configuration::load(&matches).unwrap().backend, "memory");
+
+    std::fs::write(&path, "unknown = 1\n").unwrap();
+    assert!(Configuration::load(&matches).is_err());
+    let missing = CliInput::app().get_matches_from(vec![
+      "entrylogger",
+      "--config",
+      dir.join("missing.toml").to_str().unwrap(),
+    ]);
+    assert!(Configuration::load(&missing).is_err());
+    let backend = CliInput::app().get_matches_from(vec!["entrylogger", "--backend", "paper"]);
+    assert!(Configuration::load(&backend).is_err());
+  }
+  #[test]
+  fn environment_test() {
+    let layer = ConfigurationLayer::from_vars(&|name| match name {
+      "ENTRYLOGGER_LOCK_TIMEOUT_MS" => Some("250".to_string()),
+      "ENTRYLOGGER_DEFAULT_TAG" => Some("work".to_string()),
+      _ => None,
+    })
+    .unwrap();
+    assert_eq!(layer.lock_timeout_ms, Some(250));
+    assert_eq!(layer.default_tag, Some("work".to_string()));
+    assert_eq!(
+      ConfigurationLayer::from_vars(&|name| match name {
+        "ENTRYLOGGER_LOCK_TIMEOUT_MS" => Some("soon".to_string()),
+        _ => None,
+      })
+      .map(|_layer| ()),
+      Err("Invalid ENTRYLOGGER_LOCK_TIMEOUT_MS soon: invalid digit found in string".to_string())
+    );
+    let load_mode = |value: &'static str| {
+      return ConfigurationLayer::from_vars(&move |name| match name {
+        "ENTRYLOGGER_LOAD_MODE" => Some(value.to_string()),
+        _ => None,
+      })
+      .map(|layer| layer.load_mode);
+    };
+    assert_eq!(load_mode("lenient"), Ok(Some(LoadMode::Lenient)));
+    assert_eq!(
+      load_mode("Lenient"),
+      Err("Invalid ENTRYLOGGER_LOAD_MODE Lenient, expected strict or lenient".to_string())
+    );
+  }
+}
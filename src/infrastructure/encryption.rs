@@ -0,0 +1,384 @@
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+use domain::*;
+
+use super::file_lock::FileLock;
+use super::persistence_textfile::{
+  lock_path_string, EntryStringConverter, FileSystemConfiguration,
+};
+
+pub static ENCRYPTED_PREFIX: &str = "enc:";
+static PASSPHRASE_ENV: &str = "ENTRYLOGGER_PASSPHRASE";
+// encrypted with the derived key, tells a wrong passphrase apart from a damaged line
+static CHECK_VALUE: &str = "entrylogger";
+const NONCE_LENGTH: usize = 24;
+const SALT_LENGTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PassphraseSource {
+  Prompt,
+  Env,
+  Keyfile,
+}
+
+// derived once per run and shared by every file of the journal, never printed
+#[derive(Clone)]
+pub struct EncryptionKey([u8; 32]);
+
+impl std::fmt::Debug for EncryptionKey {
+  fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+    return write!(formatter, "EncryptionKey(..)");
+  }
+}
+
+impl EncryptionKey {
+  pub fn derive(passphrase: &str, salt: &[u8]) -> Result<EncryptionKey, String> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+      .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+      .map_err(|err| format!("Couldn't derive key: {}", err))?;
+    return Ok(EncryptionKey(key));
+  }
+  // "enc:" and base64 of a random nonce followed by the ciphertext and its tag
+  pub fn encrypt(&self, plaintext: &str) -> Result<String, String> {
+    let cipher = XChaCha20Poly1305::new(&self.0.into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let mut sealed = nonce.to_vec();
+    sealed.extend(
+      cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_err| "Couldn't encrypt line".to_string())?,
+    );
+    return Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(sealed)));
+  }
+  pub fn decrypt(&self, line: &str) -> Result<String, String> {
+    let encoded = line
+      .strip_prefix(ENCRYPTED_PREFIX)
+      .ok_or_else(|| "Line isn't encrypted".to_string())?;
+    let sealed = STANDARD
+      .decode(encoded)
+      .map_err(|_err| "Invalid encrypted line".to_string())?;
+    if sealed.len() < NONCE_LENGTH {
+      return Err("Invalid encrypted line".to_string());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+    let plaintext = XChaCha20Poly1305::new(&self.0.into())
+      .decrypt(XNonce::from_slice(nonce), ciphertext)
+      .map_err(|_err| "Couldn't decrypt line, wrong key or changed line".to_string())?;
+    return String::from_utf8(plaintext).map_err(|_err| "Invalid UTF-8".to_string());
+  }
+}
+
+// the salt of the key and the check value, next to the journal as e.g. entries.keycheck
+#[derive(Serialize, Deserialize)]
+struct KeyCheck {
+  salt: String,
+  check: String,
+  // set until the lines written before encryption was turned on are encrypted as well
+  #[serde(default)]
+  encrypting: bool,
+}
+
+pub fn key_check_path_string(config: &FileSystemConfiguration) -> String {
+  return format!("{}/{}.keycheck", config.file_path, config.file_name);
+}
+
+// the first unlock picks a salt and writes the key check, later ones verify the passphrase with
+// it, the journal is left to encrypt_plaintext until it holds no plain text anymore
+pub fn unlock(
+  config: &FileSystemConfiguration,
+  source: PassphraseSource,
+  key_file: Option<&str>,
+  encrypt_plaintext: &dyn Fn(&EncryptionKey) -> Result<usize, String>,
+) -> Result<EncryptionKey, String> {
+  let path = key_check_path_string(config);
+  let key = if Path::new(path.as_str()).exists() {
+    let passphrase = read_passphrase(source, key_file, false)?;
+    verify_key_check(path.as_str(), passphrase.as_str())?
+  } else {
+    create_key_check(config, read_passphrase(source, key_file, true)?.as_str())?
+  };
+  if !read_key_check(path.as_str())?.encrypting {
+    return Ok(key);
+  }
+  let _lock = FileLock::acquire(
+    lock_path_string(config).as_str(),
+    true,
+    config.lock_timeout_ms,
+  )?;
+  // another entrylogger may have finished it while this one waited for the lock
+  let mut key_check = read_key_check(path.as_str())?;
+  if key_check.encrypting {
+    encrypt_plaintext(&key)?;
+    key_check.encrypting = false;
+    write_key_check(config, &key_check)?;
+  }
+  return Ok(key);
+}
+
+fn create_key_check(
+  config: &FileSystemConfiguration,
+  passphrase: &str,
+) -> Result<EncryptionKey, String> {
+  let path = key_check_path_string(config);
+  let mut salt = [0u8; SALT_LENGTH];
+  OsRng.fill_bytes(&mut salt);
+  let key = EncryptionKey::derive(passphrase, &salt)?;
+  let key_check = KeyCheck {
+    salt: STANDARD.encode(salt),
+    check: key.encrypt(CHECK_VALUE)?,
+    encrypting: true,
+  };
+  let content = serde_json::to_string(&key_check).map_err(|err| err.to_string())?;
+  let _lock = FileLock::acquire(
+    lock_path_string(config).as_str(),
+    true,
+    config.lock_timeout_ms,
+  )?;
+  // another entrylogger may have created it since, its salt wins
+  let mut file = match OpenOptions::new()
+    .write(true)
+    .create_new(true)
+    .open(Path::new(path.as_str()))
+  {
+    Ok(file) => file,
+    Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+      return verify_key_check(path.as_str(), passphrase);
+    }
+    Err(err) => return Err(format!("Couldn't write {}: {}", path, err)),
+  };
+  file
+    .write_all(content.as_bytes())
+    .and_then(|_ok| file.sync_all())
+    .map_err(|err| format!("Couldn't write {}: {}", path, err))?;
+  sync_directory(config);
+  return Ok(key);
+}
+
+// replaced through a temp file, the caller holds the lock
+fn write_key_check(config: &FileSystemConfiguration, key_check: &KeyCheck) -> Result<(), String> {
+  let path = key_check_path_string(config);
+  let temp = format!("{}.tmp", path);
+  let content = serde_json::to_string(key_check).map_err(|err| err.to_string())?;
+  let mut file = File::create(Path::new(temp.as_str()))
+    .map_err(|err| format!("Couldn't write {}: {}", temp, err))?;
+  file
+    .write_all(content.as_bytes())
+    .and_then(|_ok| file.sync_all())
+    .and_then(|_ok| std::fs::rename(Path::new(temp.as_str()), Path::new(path.as_str())))
+    .map_err(|err| format!("Couldn't write {}: {}", path, err))?;
+  sync_directory(config);
+  return Ok(());
+}
+
+fn sync_directory(config: &FileSystemConfiguration) {
+  // not supported everywhere
+  if let Ok(dir) = File::open(Path::new(config.file_path.as_str())) {
+    let _ = dir.sync_all();
+  }
+}
+
+fn read_key_check(path: &str) -> Result<KeyCheck, String> {
+  let content = std::fs::read_to_string(Path::new(path))
+    .map_err(|err| format!("Couldn't read {}: {}", path, err))?;
+  return serde_json::from_str(content.as_str())
+    .map_err(|err| format!("Invalid {}: {}", path, err));
+}
+
+fn verify_key_check(path: &str, passphrase: &str) -> Result<EncryptionKey, String> {
+  let key_check = read_key_check(path)?;
+  let salt = STANDARD
+    .decode(key_check.salt)
+    .map_err(|err| format!("Invalid {}: {}", path, err))?;
+  let key = EncryptionKey::derive(passphrase, &salt)?;
+  if key.decrypt(key_check.check.as_str()).ok().as_deref() != Some(CHECK_VALUE) {
+    return Err(format!("Wrong passphrase for {}", path));
+  }
+  return Ok(key);
+}
+
+// a new passphrase is asked for twice when prompting
+fn read_passphrase(
+  source: PassphraseSource,
+  key_file: Option<&str>,
+  new: bool,
+) -> Result<String, String> {
+  let passphrase = match source {
+    PassphraseSource::Prompt => {
+      let passphrase = rpassword::prompt_password("Passphrase: ")
+        .map_err(|err| format!("Couldn't read passphrase: {}", err))?;
+      if new
+        && rpassword::prompt_password("Repeat passphrase: ")
+          .map_err(|err| format!("Couldn't read passphrase: {}", err))?
+          != passphrase
+      {
+        return Err("Passphrases don't match".to_string());
+      }
+      passphrase
+    }
+    PassphraseSource::Env => {
+      env::var(PASSPHRASE_ENV).map_err(|_err| format!("{} isn't set", PASSPHRASE_ENV))?
+    }
+    PassphraseSource::Keyfile => {
+      let path = key_file.ok_or_else(|| "encryption_key_file isn't set".to_string())?;
+      std::fs::read_to_string(Path::new(path))
+        .map_err(|err| format!("Couldn't read {}: {}", path, err))?
+        .trim_end_matches(['\r', '\n'])
+        .to_string()
+    }
+  };
+  if passphrase.is_empty() {
+    return Err("Empty passphrase".to_string());
+  }
+  return Ok(passphrase);
+}
+
+// wraps the line format of the journal, lines from before encryption are still read as they are
+pub struct EncryptedConverter {
+  inner: Box<dyn EntryStringConverter>,
+  key: EncryptionKey,
+}
+
+impl EncryptedConverter {
+  pub fn new(inner: Box<dyn EntryStringConverter>, key: EncryptionKey) -> EncryptedConverter {
+    return EncryptedConverter { inner, key };
+  }
+}
+
+impl EntryStringConverter for EncryptedConverter {
+  fn parse_string(&self, entry_string: String) -> Result<EntryObject, String> {
+    if entry_string.starts_with(ENCRYPTED_PREFIX) {
+      return self
+        .inner
+        .parse_string(self.key.decrypt(entry_string.as_str())?);
+    }
+    return self.inner.parse_string(entry_string);
+  }
+  fn serialize_entry(&self, entry: EntryObject) -> Result<String, String> {
+    return self
+      .key
+      .encrypt(self.inner.serialize_entry(entry)?.as_str());
+  }
+}
+
+/* -----------------------------------TESTS------------------------------------------ */
+#[cfg(test)]
+mod tests {
+  use super::super::test_support::test_dir;
+  use super::*;
+  #[test]
+  fn encrypt_line_test() {
+    let key = EncryptionKey::derive("correct horse", b"0123456789abcdef").unwrap();
+    let line = key
+      .encrypt("v2 [ins:1 date:13-oct-20 time:morning tag:fit] run")
+      .unwrap();
+    assert!(line.starts_with(ENCRYPTED_PREFIX));
+    assert!(!line.contains("morning"));
+    assert_eq!(
+      key.decrypt(line.as_str()).unwrap(),
+      "v2 [ins:1 date:13-oct-20 time:morning tag:fit] run"
+    );
+    // the same line never encrypts the same way twice
+    assert_ne!(key.encrypt("run").unwrap(), key.encrypt("run").unwrap());
+
+    let other = EncryptionKey::derive("wrong horse", b"0123456789abcdef").unwrap();
+    assert!(other.decrypt(line.as_str()).is_err());
+    let mut changed = line.clone().into_bytes();
+    let last = changed.len() - 3;
+    changed[last] = if changed[last] == b'A' { b'B' } else { b'A' };
+    assert!(key
+      .decrypt(String::from_utf8(changed).unwrap().as_str())
+      .is_err());
+  }
+  #[test]
+  fn unlock_test() {
+    let dir = test_dir("unlock");
+    let config = FileSystemConfiguration {
+      file_path: dir.to_str().unwrap().to_owned(),
+      ..FileSystemConfiguration::default()
+    };
+    let key_file = dir.join("passphrase");
+    let key_file = key_file.to_str().unwrap();
+    std::fs::write(key_file, "correct horse\n").unwrap();
+    let encrypted = std::cell::Cell::new(0);
+    let encrypt_plaintext = |_key: &EncryptionKey| -> Result<usize, String> {
+      encrypted.set(encrypted.get() + 1);
+      return Ok(1);
+    };
+    let interrupted = |_key: &EncryptionKey| -> Result<usize, String> {
+      return Err("Couldn't write entries.log".to_string());
+    };
+    assert_eq!(
+      unlock(
+        &config,
+        PassphraseSource::Keyfile,
+        Some(key_file),
+        &interrupted
+      )
+      .map(|_key| ()),
+      Err("Couldn't write entries.log".to_string())
+    );
+    // the journal is encrypted on the next unlock, then never again
+    let key = unlock(
+      &config,
+      PassphraseSource::Keyfile,
+      Some(key_file),
+      &encrypt_plaintext,
+    )
+    .unwrap();
+    let line = key.encrypt("run").unwrap();
+    // the salt was kept, the same passphrase gives the same key
+    let again = unlock(
+      &config,
+      PassphraseSource::Keyfile,
+      Some(key_file),
+      &encrypt_plaintext,
+    )
+    .unwrap();
+    assert_eq!(again.decrypt(line.as_str()).unwrap(), "run");
+    assert_eq!(encrypted.get(), 1);
+    std::fs::write(key_file, "wrong horse").unwrap();
+    assert_eq!(
+      unlock(
+        &config,
+        PassphraseSource::Keyfile,
+        Some(key_file),
+        &encrypt_plaintext
+      )
+      .map(|_key| ()),
+      Err(format!(
+        "Wrong passphrase for {}",
+        key_check_path_string(&config)
+      ))
+    );
+    assert_eq!(
+      unlock(&config, PassphraseSource::Keyfile, None, &encrypt_plaintext).map(|_key| ()),
+      Err("encryption_key_file isn't set".to_string())
+    );
+    // a key check created in the meantime is verified instead of overwritten
+    assert_eq!(
+      create_key_check(&config, "correct horse")
+        .unwrap()
+        .decrypt(line.as_str())
+        .unwrap(),
+      "run"
+    );
+    assert_eq!(
+      create_key_check(&config, "wrong horse").map(|_key| ()),
+      Err(format!(
+        "Wrong passphrase for {}",
+        key_check_path_string(&config)
+      ))
+    );
+  }
+}
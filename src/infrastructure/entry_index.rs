@@ -0,0 +1,180 @@
+use std::fs::{rename, File, OpenOptions};
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use chrono::NaiveDate;
+
+use domain::*;
+
+// a stamp line is far shorter, appends only read this much of the index
+const STAMP_TAIL_SIZE: u64 = 4096;
+
+// size and modification time of the journal the index was written for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexStamp {
+  pub size: u64,
+  pub modified: u128,
+  pub lines: usize,
+}
+
+// lines that can't be parsed are indexed without ins, date and tag and are always read
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexRecord {
+  pub line: usize,
+  pub offset: u64,
+  pub length: u64,
+  pub ins: Option<String>,
+  pub date: Option<String>,
+  pub tag: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum IndexLine {
+  Record(IndexRecord),
+  Stamp(IndexStamp),
+}
+
+pub struct EntryIndex {
+  pub records: Vec<IndexRecord>,
+  pub stamp: IndexStamp,
+}
+
+impl IndexStamp {
+  pub fn of(path: &str, lines: usize) -> Result<IndexStamp, String> {
+    let metadata = std::fs::metadata(Path::new(path))
+      .map_err(|err| format!("Couldn't read {}: {}", path, err))?;
+    let modified = metadata
+      .modified()
+      .map_err(|err| format!("Couldn't read {}: {}", path, err))?
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |duration| duration.as_nanos());
+    return Ok(IndexStamp {
+      size: metadata.len(),
+      modified,
+      lines,
+    });
+  }
+  fn describes(&self, other: &IndexStamp) -> bool {
+    return self.size == other.size && self.modified == other.modified;
+  }
+}
+
+impl IndexRecord {
+  pub fn new(line: usize, offset: u64, length: u64, entry: Option<&EntryObject>) -> IndexRecord {
+    let date = entry.and_then(|entry| {
+      NaiveDate::parse_from_str(entry.metadata.date.as_str(), "%d-%b-%y")
+        .ok()
+        .map(|date| date.format("%Y-%m-%d").to_string())
+    });
+    return IndexRecord {
+      line,
+      offset,
+      length,
+      ins: entry.and_then(|entry| entry.metadata.ins.clone()),
+      date,
+      tag: entry.map(|entry| entry.metadata.tag.clone()),
+    };
+  }
+  // only what the index knows, the entries still go through EntryFilter::matches
+  pub fn matches(&self, filter: &EntryFilter) -> bool {
+    let date = match &self.date {
+      Some(date) => date,
+      None => return true,
+    };
+    if let Some(from) = filter.date_from {
+      if *date < from.format("%Y-%m-%d").to_string() {
+        return false;
+      }
+    }
+    if let Some(to) = filter.date_to {
+      if *date > to.format("%Y-%m-%d").to_string() {
+        return false;
+      }
+    }
+    if let Some(tag) = &filter.tag {
+      if self.tag.as_ref() != Some(tag) {
+        return false;
+      }
+    }
+    return true;
+  }
+  pub fn may_have_ins(&self, ins: &str) -> bool {
+    return self.date.is_none() || self.ins.as_deref() == Some(ins);
+  }
+}
+
+impl EntryIndex {
+  // None when the index is missing, unreadable or was written for another version of the journal
+  pub fn load(path: &str, current: &IndexStamp) -> Option<EntryIndex> {
+    let content = std::fs::read_to_string(Path::new(path)).ok()?;
+    let mut records: Vec<IndexRecord> = vec![];
+    let mut stamp: Option<IndexStamp> = None;
+    for line in content.lines() {
+      match serde_json::from_str::<IndexLine>(line).ok()? {
+        IndexLine::Record(record) => {
+          records.push(record);
+          stamp = None;
+        }
+        IndexLine::Stamp(line_stamp) => stamp = Some(line_stamp),
+      }
+    }
+    let stamp = stamp?;
+    if !stamp.describes(current) {
+      return None;
+    }
+    return Some(EntryIndex { records, stamp });
+  }
+  // only reads the last line, appends don't need the records
+  pub fn load_stamp(path: &str, current: &IndexStamp) -> Option<IndexStamp> {
+    let mut file = File::open(Path::new(path)).ok()?;
+    let length = file.metadata().ok()?.len();
+    let mut tail: Vec<u8> = vec![];
+    file
+      .seek(SeekFrom::Start(length.saturating_sub(STAMP_TAIL_SIZE)))
+      .and_then(|_position| file.read_to_end(&mut tail))
+      .ok()?;
+    let tail = String::from_utf8_lossy(&tail);
+    let last = tail.strip_suffix('\n')?.rsplit('\n').next()?;
+    match serde_json::from_str::<IndexLine>(last).ok()? {
+      IndexLine::Stamp(stamp) if stamp.describes(current) => return Some(stamp),
+      _ => return None,
+    }
+  }
+  // written next to the index and renamed, readers rebuilding at the same time don't collide
+  pub fn write(&self, path: &str) -> Result<(), String> {
+    let mut content = String::new();
+    for record in &self.records {
+      content.push_str(Self::format_line(IndexLine::Record(record.clone()))?.as_str());
+    }
+    content.push_str(Self::format_line(IndexLine::Stamp(self.stamp.clone()))?.as_str());
+    let temp_path = format!("{}.{}.tmp", path, std::process::id());
+    std::fs::write(Path::new(temp_path.as_str()), content)
+      .and_then(|_ok| rename(Path::new(temp_path.as_str()), Path::new(path)))
+      .map_err(|err| format!("Couldn't write {}: {}", path, err))?;
+    return Ok(());
+  }
+  pub fn append(path: &str, records: &[IndexRecord], stamp: IndexStamp) -> Result<(), String> {
+    let mut content = String::new();
+    for record in records {
+      content.push_str(Self::format_line(IndexLine::Record(record.clone()))?.as_str());
+    }
+    content.push_str(Self::format_line(IndexLine::Stamp(stamp))?.as_str());
+    let mut file = OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(Path::new(path))
+      .map_err(|err| format!("Couldn't open {}: {}", path, err))?;
+    file
+      .write_all(content.as_bytes())
+      .map_err(|err| format!("Couldn't write {}: {}", path, err))?;
+    return Ok(());
+  }
+  fn format_line(line: IndexLine) -> Result<String, String> {
+    return serde_json::to_string(&line)
+      .map(|json| json + "\n")
+      .map_err(|err| err.to_string());
+  }
+}
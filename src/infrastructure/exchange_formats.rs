@@ -0,0 +1,251 @@
+use application::*;
+use domain::*;
+
+use serde_json::value::RawValue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExchangeFormat {
+  Json,
+  JsonLines,
+  Csv,
+}
+
+impl ExchangeFormat {
+  pub fn from_name(name: &str) -> Result<ExchangeFormat, String> {
+    match name {
+      "json" => return Ok(ExchangeFormat::Json),
+      "jsonl" => return Ok(ExchangeFormat::JsonLines),
+      "csv" => return Ok(ExchangeFormat::Csv),
+      _ => return Err("Expected one of the following formats: json, jsonl, csv".to_string()),
+    }
+  }
+}
+
+// flat representation shared by every format, keeps every metadata field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntryRecord {
+  ins: Option<String>,
+  date: String,
+  time: String,
+  tag: String,
+  message: String,
+}
+
+impl EntryRecord {
+  pub fn from_entry(entry: &EntryObject) -> EntryRecord {
+    return EntryRecord {
+      ins: entry.metadata.ins.clone(),
+      date: entry.metadata.date.clone(),
+      time: entry.metadata.time.clone(),
+      tag: entry.metadata.tag.clone(),
+      message: entry.message.clone(),
+    };
+  }
+  pub fn into_entry(self) -> EntryObject {
+    let metadata = EntryMetadata {
+      ins: self.ins,
+      date: self.date,
+      time: self.time,
+      tag: self.tag,
+    };
+    return EntryObject {
+      metadata,
+      message: self.message,
+    };
+  }
+}
+
+pub struct EntryExchange {
+  format: ExchangeFormat,
+}
+
+impl EntryExchange {
+  pub fn new(format: ExchangeFormat) -> EntryExchange {
+    return EntryExchange { format };
+  }
+  pub fn export(&self, entries: &[EntryObject]) -> Result<String, String> {
+    let records: Vec<EntryRecord> = entries.iter().map(EntryRecord::from_entry).collect();
+    match self.format {
+      ExchangeFormat::Json => {
+        let mut content = serde_json::to_string_pretty(&records).map_err(|err| err.to_string())?;
+        content.push('\n');
+        return Ok(content);
+      }
+      ExchangeFormat::JsonLines => {
+        let mut content = String::new();
+        for record in records {
+          content.push_str(
+            serde_json::to_string(&record)
+              .map_err(|err| err.to_string())?
+              .as_str(),
+          );
+          content.push('\n');
+        }
+        return Ok(content);
+      }
+      ExchangeFormat::Csv => {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        // headers are written with the first record, an empty export still gets them
+        if records.is_empty() {
+          writer
+            .write_record(["ins", "date", "time", "tag", "message"])
+            .map_err(|err| err.to_string())?;
+        }
+        for record in records {
+          writer.serialize(record).map_err(|err| err.to_string())?;
+        }
+        let bytes = writer.into_inner().map_err(|err| err.to_string())?;
+        return String::from_utf8(bytes).map_err(|err| err.to_string());
+      }
+    }
+  }
+  // rows that can't be read as an entry are returned as errors with their line number
+  pub fn import(&self, content: &str) -> Result<Vec<ImportRow>, String> {
+    let mut rows: Vec<ImportRow> = vec![];
+    match self.format {
+      ExchangeFormat::Json => {
+        let values: Vec<&RawValue> =
+          serde_json::from_str(content).map_err(|err| format!("Invalid json: {}", err))?;
+        for value in values {
+          let offset = value.get().as_ptr() as usize - content.as_ptr() as usize;
+          let line = content[..offset].matches('\n').count() + 1;
+          let entry = serde_json::from_str::<EntryRecord>(value.get())
+            .map(EntryRecord::into_entry)
+            .map_err(|err| err.to_string());
+          rows.push(ImportRow { line, entry });
+        }
+      }
+      ExchangeFormat::JsonLines => {
+        for (index, raw_line) in content.lines().enumerate() {
+          if raw_line.trim().is_empty() {
+            continue;
+          }
+          let entry = serde_json::from_str::<EntryRecord>(raw_line)
+            .map(EntryRecord::into_entry)
+            .map_err(|err| err.to_string());
+          rows.push(ImportRow {
+            line: index + 1,
+            entry,
+          });
+        }
+      }
+      ExchangeFormat::Csv => {
+        let mut reader = csv::Reader::from_reader(content.as_bytes());
+        let headers = reader
+          .headers()
+          .map_err(|err| format!("Invalid csv header: {}", err))?
+          .clone();
+        for result in reader.records() {
+          match result {
+            Ok(record) => {
+              let line = record.position().map_or(0, |position| position.line()) as usize;
+              let entry = record
+                .deserialize::<EntryRecord>(Some(&headers))
+                .map(EntryRecord::into_entry)
+                .map_err(|err| err.to_string());
+              rows.push(ImportRow { line, entry });
+            }
+            Err(err) => {
+              let line = err.position().map_or(0, |position| position.line()) as usize;
+              rows.push(ImportRow {
+                line,
+                entry: Err(err.to_string()),
+              });
+            }
+          }
+        }
+      }
+    }
+    return Ok(rows);
+  }
+}
+
+/* -----------------------------------TESTS------------------------------------------ */
+#[cfg(test)]
+mod tests {
+  use super::*;
+  fn test_entries() -> Vec<EntryObject> {
+    let metadata = EntryMetadata {
+      ins: Some("0000000000000".to_string()),
+      date: "13-oct-20".to_string(),
+      time: "morning".to_string(),
+      tag: "fit".to_string(),
+    };
+    return vec![EntryObject {
+      metadata,
+      message: "aerobic, \"run\" (5/5)".to_string(),
+    }];
+  }
+  #[test]
+  fn export_test() {
+    let entries = test_entries();
+    let jsonl = EntryExchange::new(ExchangeFormat::JsonLines)
+      .export(&entries)
+      .unwrap();
+    assert_eq!(
+      jsonl,
+      "{\"ins\":\"0000000000000\",\"date\":\"13-oct-20\",\"time\":\"morning\",\"tag\":\"fit\",\"message\":\"aerobic, \\\"run\\\" (5/5)\"}\n"
+    );
+    let csv = EntryExchange::new(ExchangeFormat::Csv)
+      .export(&entries)
+      .unwrap();
+    assert_eq!(
+      csv,
+      "ins,date,time,tag,message\n0000000000000,13-oct-20,morning,fit,\"aerobic, \"\"run\"\" (5/5)\"\n"
+    );
+    let json = EntryExchange::new(ExchangeFormat::Json)
+      .export(&entries)
+      .unwrap();
+    assert!(json.starts_with("[\n  {\n    \"ins\": \"0000000000000\""));
+    let empty_csv = EntryExchange::new(ExchangeFormat::Csv).export(&[]).unwrap();
+    assert_eq!(empty_csv, "ins,date,time,tag,message\n");
+  }
+  #[test]
+  fn import_round_trip_test() {
+    let entries = test_entries();
+    for (format, line) in [
+      (ExchangeFormat::Json, 2),
+      (ExchangeFormat::JsonLines, 1),
+      (ExchangeFormat::Csv, 2),
+    ] {
+      let exchange = EntryExchange::new(format);
+      let content = exchange.export(&entries).unwrap();
+      let rows = exchange.import(content.as_str()).unwrap();
+      assert_eq!(rows.len(), 1);
+      assert_eq!(rows[0].line, line);
+      assert_eq!(rows[0].entry, Ok(entries[0].clone()));
+    }
+  }
+  #[test]
+  fn import_line_test() {
+    let jsonl = "{\"ins\":\"0000000000000\",\"date\":\"13-oct-20\",\"time\":\"morning\",\"tag\":\"fit\",\"message\":\"run\"}\n\n{\"date\":\"13-oct-20\"}\n";
+    let rows = EntryExchange::new(ExchangeFormat::JsonLines)
+      .import(jsonl)
+      .unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].line, 1);
+    assert!(rows[0].entry.is_ok());
+    assert_eq!(rows[1].line, 3);
+    assert!(rows[1].entry.is_err());
+
+    let csv = "ins,date,time,tag,message\n,13-oct-20,morning,fit,run\n0000000000000,13-oct-20\n";
+    let rows = EntryExchange::new(ExchangeFormat::Csv).import(csv).unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].line, 2);
+    assert_eq!(rows[0].entry.as_ref().unwrap().metadata.ins, None);
+    assert_eq!(rows[1].line, 3);
+    assert!(rows[1].entry.is_err());
+
+    let json = "[\n  {\"date\": \"13-oct-20\"},\n  {\"ins\": null, \"date\": \"13-oct-20\", \"time\": \"n/a\", \"tag\": \"fit\", \"message\": \"run\"}\n]";
+    let rows = EntryExchange::new(ExchangeFormat::Json)
+      .import(json)
+      .unwrap();
+    assert_eq!(rows[0].line, 2);
+    assert!(rows[0].entry.is_err());
+    assert_eq!(rows[1].line, 3);
+    assert!(rows[1].entry.is_ok());
+    assert!(EntryExchange::new(ExchangeFormat::Json)
+      .import("{")
+      .is_err());
+  }
+}
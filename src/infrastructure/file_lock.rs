@@ -0,0 +1,51 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const LOCK_RETRY_MS: u64 = 10;
+
+// advisory lock held on a sidecar file, released when dropped
+pub struct FileLock {
+  file: File,
+}
+
+impl FileLock {
+  pub fn acquire(path: &str, exclusive: bool, timeout_ms: u64) -> Result<FileLock, String> {
+    let file = OpenOptions::new()
+      .write(true)
+      .create(true)
+      .truncate(false)
+      .open(Path::new(path))
+      .map_err(|err| format!("Couldn't open lock file {}: {}", path, err))?;
+    let start = Instant::now();
+    loop {
+      let attempt = if exclusive {
+        file.try_lock()
+      } else {
+        file.try_lock_shared()
+      };
+      match attempt {
+        Ok(()) => return Ok(FileLock { file }),
+        Err(TryLockError::WouldBlock) => {
+          if start.elapsed() >= Duration::from_millis(timeout_ms) {
+            return Err(format!(
+              "Couldn't lock {} within {} ms, another entrylogger is using the journal",
+              path, timeout_ms
+            ));
+          }
+          sleep(Duration::from_millis(LOCK_RETRY_MS));
+        }
+        Err(TryLockError::Error(err)) => {
+          return Err(format!("Couldn't lock {}: {}", path, err));
+        }
+      }
+    }
+  }
+}
+
+impl Drop for FileLock {
+  fn drop(&mut self) {
+    let _ = self.file.unlock();
+  }
+}
@@ -1,11 +1,20 @@
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 
 use chrono::{prelude::*, Duration};
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use regex::Regex;
 
 use application::*;
 use domain::*;
 
+use super::configuration::*;
+use super::exchange_formats::*;
+use super::persistence_jsonlines::JsonLinesPersistence;
+use super::persistence_textfile::{
+  FileSystemConfiguration, FileSystemLoader, FileSystemPersistence,
+};
+
 struct InputParsing {}
 impl InputParsing {
   fn parse_date(&self, date: String) -> String {
@@ -49,25 +58,118 @@ impl InputParsing {
   }
 }
 
+struct QuickAddParsing {}
+impl QuickAddParsing {
+  fn is_date(token: &str) -> bool {
+    return token == "today"
+      || token == "yesterday"
+      || NaiveDate::parse_from_str(token, "%d-%b-%y").is_ok();
+  }
+  fn is_time(token: &str) -> bool {
+    return token == "now" || TIME_VEC.contains(&token);
+  }
+  // <date>? <time>? #<tag> <message>, date and time default to today and now
+  fn parse(
+    &self,
+    input_parsing: &InputParsing,
+    line: &str,
+    default_tag: Option<&str>,
+  ) -> Result<EntryObject, String> {
+    lazy_static! {
+      static ref TOKEN_REGEX: Regex = Regex::new(r"\S+").unwrap();
+    };
+    let mut date: Option<&str> = None;
+    let mut time: Option<&str> = None;
+    let mut tag: Option<&str> = None;
+    let mut message: Option<&str> = None;
+    for token in TOKEN_REGEX.find_iter(line) {
+      let word = token.as_str();
+      if let Some(stripped) = word.strip_prefix('#') {
+        if message.is_some() {
+          return Err(format!("Tag '{}' must come before the message", word));
+        }
+        if stripped.is_empty() {
+          return Err("Empty tag after '#'".to_string());
+        }
+        if let Some(previous) = tag {
+          return Err(format!(
+            "Only one tag allowed, found '#{}' and '{}'",
+            previous, word
+          ));
+        }
+        tag = Some(stripped);
+        continue;
+      }
+      if message.is_some() {
+        continue;
+      }
+      if tag.is_none() && Self::is_date(word) {
+        if let Some(previous) = date {
+          return Err(format!("Date given twice: '{}' and '{}'", previous, word));
+        }
+        date = Some(word);
+        continue;
+      }
+      if tag.is_none() && Self::is_time(word) {
+        if let Some(previous) = time {
+          return Err(format!("Time given twice: '{}' and '{}'", previous, word));
+        }
+        time = Some(word);
+        continue;
+      }
+      if tag.is_none() && default_tag.is_none() {
+        return Err(format!(
+          "Expected a date, a time or a #tag before the message, found '{}'",
+          word
+        ));
+      }
+      message = Some(line[token.start()..].trim_end());
+    }
+    let tag = match tag.or(default_tag) {
+      Some(tag) => tag,
+      None => return Err("Missing #tag".to_string()),
+    };
+    let message = match message {
+      Some(message) => message,
+      None => return Err(format!("Missing message after '#{}'", tag)),
+    };
+    let entry_metadata = EntryMetadata {
+      ins: None,
+      date: input_parsing.parse_date(date.unwrap_or("today").to_string()),
+      time: input_parsing.parse_time(time.unwrap_or("now").to_string()),
+      tag: tag.to_string(),
+    };
+    return Ok(EntryObject {
+      metadata: entry_metadata,
+      message: message.to_string(),
+    });
+  }
+}
+
 pub struct CliInput {
   input_parsing: InputParsing,
+  quick_add_parsing: QuickAddParsing,
   entry_business: EntryBusiness,
   entry_controller: EntryController,
+  configuration: Configuration,
 }
 impl CliInput {
   fn parse_new_line(str: String) -> String {
     return str
-      .trim_end_matches("\n")
-      .trim_end_matches("\r")
+      .trim_end_matches('\n')
+      .trim_end_matches('\r')
       .to_string();
   }
-  pub fn new(entry_controller: EntryController) -> CliInput {
+  pub fn new(entry_controller: EntryController, configuration: Configuration) -> CliInput {
     let input_parsing = InputParsing {};
+    let quick_add_parsing = QuickAddParsing {};
     let entry_business = EntryBusiness {};
     return CliInput {
       input_parsing,
+      quick_add_parsing,
       entry_business,
       entry_controller,
+      configuration,
     };
   }
   fn read_date(&self) -> Result<String, String> {
@@ -95,6 +197,9 @@ impl CliInput {
     }
   }
   fn read_tag(&self) -> Result<String, String> {
+    if let Some(default_tag) = &self.configuration.default_tag {
+      return self.read_with_default("tag", default_tag.as_str());
+    }
     print!("tag > ");
     std::io::stdout().flush().expect("flush failed");
     let mut buf = String::new();
@@ -116,98 +221,1141 @@ impl CliInput {
       Err(_error) => return Err("couldn't read message".to_string()),
     }
   }
-  pub fn listen(&self) -> Result<EntryObject, String> {
-    let matches = App::new("entrylogger")
+  fn read_with_default(&self, label: &str, current: &str) -> Result<String, String> {
+    print!("{} [{}] > ", label, current);
+    std::io::stdout().flush().expect("flush failed");
+    let mut buf = String::new();
+    match std::io::stdin().read_line(&mut buf) {
+      Ok(_n) => {
+        let value = Self::parse_new_line(buf);
+        if value.is_empty() {
+          return Ok(current.to_string());
+        }
+        return Ok(value);
+      }
+      Err(_error) => return Err(format!("couldn't read {}", label)),
+    }
+  }
+  fn validate_date_input(&self, date: &str) -> Result<String, String> {
+    return self
+      .entry_business
+      .validate_date(date)
+      .map_err(|err| match err.code() {
+        DomainErrorCode::InvalidFormat => {
+          "Expected one of the following: today, yesterday or <dd-mon-yy> format".to_string()
+        }
+        _ => "Couldn't parse date.".to_string(),
+      });
+  }
+  fn validate_time_input(&self, time: &str) -> Result<String, String> {
+    return self
+      .entry_business
+      .validate_time(time)
+      .map_err(|err| match err.code() {
+        DomainErrorCode::InvalidFormat => {
+          "Expected one of the following: morning, afternoon, night, latenight, n/a or now"
+            .to_string()
+        }
+        _ => "Couldn't parse time.".to_string(),
+      });
+  }
+  fn validate_tag_input(&self, tag: &str) -> Result<String, String> {
+    return self.entry_business.validate_tag(tag).map_err(|err| {
+      match err.code() {
+        DomainErrorCode::InvalidFormat => {
+          "Only lowercase alphanumerical characters allowed in tag".to_string()
+        }
+        DomainErrorCode::MaxLengthExceeded => "Maximum length allowed for tag: 12".to_string(), // TODO: better error handling
+        _ => "Couldn't parse tag.".to_string(),
+      }
+    });
+  }
+  fn validate_message_input(&self, message: &str) -> Result<String, String> {
+    return self
+      .entry_business
+      .validate_message(message)
+      .map_err(|err| {
+        match err.code() {
+          DomainErrorCode::InvalidFormat => "Invalid characters found in message".to_string(),
+          DomainErrorCode::MaxLengthExceeded => {
+            "Maximum length allowed for message: 32".to_string()
+          } // TODO: better error handling
+          _ => "Couldn't parse message.".to_string(),
+        }
+      });
+  }
+  fn parse_date_arg(&self, date: &str) -> Result<NaiveDate, String> {
+    let date =
+      self.validate_date_input(self.input_parsing.parse_date(date.to_string()).as_str())?;
+    return NaiveDate::parse_from_str(date.as_str(), "%d-%b-%y").map_err(|err| err.to_string());
+  }
+  fn format_entry(entry: &EntryObject) -> String {
+    return format!(
+      "{} {} {:<9} {:<12} {}",
+      entry.metadata.ins.as_deref().unwrap_or("-"),
+      entry.metadata.date,
+      entry.metadata.time,
+      entry.metadata.tag,
+      entry.message
+    );
+  }
+  fn filter_args() -> Vec<Arg<'static, 'static>> {
+    return vec![
+      Arg::with_name("from")
+        .long("from")
+        .takes_value(true)
+        .help("first date to include: today, yesterday or <dd-mon-yy>"),
+      Arg::with_name("to")
+        .long("to")
+        .takes_value(true)
+        .help("last date to include: today, yesterday or <dd-mon-yy>"),
+      Arg::with_name("tag")
+        .long("tag")
+        .takes_value(true)
+        .help("only entries with this tag"),
+      Arg::with_name("time")
+        .long("time")
+        .takes_value(true)
+        .help("only entries with this time: morning, afternoon, night, latenight, n/a or now"),
+    ];
+  }
+  fn highlight(text: &str, spans: &[(usize, usize)], color: bool) -> String {
+    if !color {
+      return text.to_string();
+    }
+    let mut result = String::new();
+    let mut last = 0;
+    for (start, end) in spans {
+      result.push_str(&text[last..*start]);
+      result.push_str("\x1b[1;31m");
+      result.push_str(&text[*start..*end]);
+      result.push_str("\x1b[0m");
+      last = *end;
+    }
+    result.push_str(&text[last..]);
+    return result;
+  }
+  fn format_hit(hit: &SearchHit, color: bool) -> String {
+    let padding = " ".repeat(12usize.saturating_sub(hit.entry.metadata.tag.len()));
+    return format!(
+      "{} {} {:<9} {}{} {}",
+      hit.entry.metadata.ins.as_deref().unwrap_or("-"),
+      hit.entry.metadata.date,
+      hit.entry.metadata.time,
+      Self::highlight(hit.entry.metadata.tag.as_str(), &hit.tag_spans, color),
+      padding,
+      Self::highlight(hit.entry.message.as_str(), &hit.message_spans, color)
+    );
+  }
+  fn message_filter_arg() -> Arg<'static, 'static> {
+    return Arg::with_name("message")
+      .long("message")
+      .takes_value(true)
+      .help("only entries whose message contains this text");
+  }
+  pub fn app() -> App<'static, 'static> {
+    return App::new("entrylogger")
+      .arg(
+        Arg::with_name("config")
+          .long("config")
+          .takes_value(true)
+          .global(true)
+          .help("configuration file, overrides $ENTRYLOGGER_CONFIG"),
+      )
+      .arg(
+        Arg::with_name("backend")
+          .long("backend")
+          .takes_value(true)
+          .global(true)
+          .help("persistence backend, overrides $ENTRYLOGGER_BACKEND"),
+      )
+      .arg(
+        Arg::with_name("file-path")
+          .long("file-path")
+          .takes_value(true)
+          .global(true)
+          .help("journal directory, overrides $ENTRYLOGGER_FILE_PATH"),
+      )
+      .arg(
+        Arg::with_name("file-name")
+          .long("file-name")
+          .takes_value(true)
+          .global(true)
+          .help("journal file name without extension, overrides $ENTRYLOGGER_FILE_NAME"),
+      )
+      .arg(
+        Arg::with_name("default-tag")
+          .long("default-tag")
+          .takes_value(true)
+          .global(true)
+          .help("tag used when none is given, overrides $ENTRYLOGGER_DEFAULT_TAG"),
+      )
+      .arg(
+        Arg::with_name("lenient").long("lenient").global(true).help(
+          "skips malformed journal lines instead of failing, overrides $ENTRYLOGGER_LOAD_MODE",
+        ),
+      )
       .arg(
         Arg::with_name("post")
           .long("post")
           .short("p")
           .help("posting"),
       )
-      .get_matches();
-    if matches.is_present("post") {
-      let date = self
-        .entry_business
-        .validate_date(self.read_date().unwrap().as_str())
-        .or_else(|err| match err.code() {
-          DomainErrorCode::InvalidFormat => {
-            return Err("Expected one of the following: today, yesterday or <dd-mon-yy> format")
-          }
-          _ => return Err("Couldn't parse date."),
-        })?;
-      let time = self
-        .entry_business
-        .validate_time(self.read_time().unwrap().as_str())
-        .or_else(|err| match err.code() {
-          DomainErrorCode::InvalidFormat => {
-            return Err(
-              "Expected one of the following: morning, afternoon, night, latenight, n/a or now",
-            )
-          }
-          _ => return Err("Couldn't parse time."),
-        })?;
-      let tag = self
-        .entry_business
-        .validate_tag(self.read_tag().unwrap().as_str())
-        .or_else(|err| {
-          match err.code() {
-            DomainErrorCode::InvalidFormat => {
-              return Err("Only lowercase alphanumerical characters allowed in tag")
-            }
-            DomainErrorCode::MaxLengthExceeded => return Err("Maximum length allowed for tag: 12"), // TODO: better error handling
-            _ => return Err("Couldn't parse tag."),
-          }
-        })?;
-      let message = self
-        .entry_business
-        .validate_message(self.read_message().unwrap().as_str())
-        .or_else(|err| {
-          match err.code() {
-            DomainErrorCode::InvalidFormat => return Err("Invalid characters found in message"),
-            DomainErrorCode::MaxLengthExceeded => {
-              return Err("Maximum length allowed for message: 32")
-            } // TODO: better error handling
-            _ => return Err("Couldn't parse message."),
-          }
-        })?;
-      let entry_metadata = EntryMetadata {
-        date,
-        time,
-        ins: None,
-        tag,
-      };
-      let entry_object = EntryObject {
-        metadata: entry_metadata,
-        message,
-      };
-      return self.entry_controller.post_entry(entry_object);
+      .arg(
+        Arg::with_name("date")
+          .long("date")
+          .takes_value(true)
+          .requires("post")
+          .help("entry date: today, yesterday or <dd-mon-yy>"),
+      )
+      .arg(
+        Arg::with_name("time")
+          .long("time")
+          .takes_value(true)
+          .requires("post")
+          .help("entry time: morning, afternoon, night, latenight, n/a or now"),
+      )
+      .arg(
+        Arg::with_name("tag")
+          .long("tag")
+          .takes_value(true)
+          .requires("post")
+          .help("entry tag"),
+      )
+      .arg(
+        Arg::with_name("message")
+          .long("message")
+          .takes_value(true)
+          .requires("post")
+          .help("entry message"),
+      )
+      .arg(
+        Arg::with_name("no-input")
+          .long("no-input")
+          .requires("post")
+          .help("fails instead of prompting for missing fields"),
+      )
+      .subcommand(
+        SubCommand::with_name("add")
+          .about("posts an entry from a single line: [date] [time] #tag message")
+          .arg(
+            Arg::with_name("line")
+              .required(true)
+              .help("for example: \"yesterday night #fit aerobic (5/5)\""),
+          ),
+      )
+      .subcommand(
+        SubCommand::with_name("backups")
+          .about("inspects the journal backups")
+          .subcommand(
+            SubCommand::with_name("list")
+              .about("lists backups, newest first, with entry counts and sizes"),
+          ),
+      )
+      .subcommand(
+        SubCommand::with_name("compact")
+          .about("rewrites the journal in sorted order, the previous journal is kept as backup")
+          .arg(
+            Arg::with_name("archive")
+              .long("archive")
+              .help("gzips the backups and, when sharded, the journals of past periods"),
+          ),
+      )
+      .subcommand(
+        SubCommand::with_name("config")
+          .about("inspects the configuration")
+          .subcommand(SubCommand::with_name("show").about("prints the effective configuration")),
+      )
+      .subcommand(
+        SubCommand::with_name("delete")
+          .about("removes entries after confirmation, the previous journal is kept as backup")
+          .arg(
+            Arg::with_name("ins")
+              .required(true)
+              .multiple(true)
+              .help("ins of the entries to remove"),
+          )
+          .arg(
+            Arg::with_name("yes")
+              .long("yes")
+              .short("y")
+              .help("skips the confirmation prompt"),
+          ),
+      )
+      .subcommand(
+        SubCommand::with_name("edit")
+          .about("changes an existing entry, prompting for every field when no field is given")
+          .arg(
+            Arg::with_name("ins")
+              .required(true)
+              .help("ins of the entry to change"),
+          )
+          .arg(
+            Arg::with_name("date")
+              .long("date")
+              .takes_value(true)
+              .help("new date: today, yesterday or <dd-mon-yy>"),
+          )
+          .arg(
+            Arg::with_name("time")
+              .long("time")
+              .takes_value(true)
+              .help("new time: morning, afternoon, night, latenight, n/a or now"),
+          )
+          .arg(
+            Arg::with_name("tag")
+              .long("tag")
+              .takes_value(true)
+              .help("new tag"),
+          )
+          .arg(
+            Arg::with_name("message")
+              .long("message")
+              .takes_value(true)
+              .help("new message"),
+          ),
+      )
+      .subcommand(
+        SubCommand::with_name("search")
+          .about("searches tags and messages, optionally filtered")
+          .arg(
+            Arg::with_name("pattern")
+              .required(true)
+              .help("text to look for, or a regex with --regex"),
+          )
+          .arg(
+            Arg::with_name("ignore-case")
+              .long("ignore-case")
+              .short("i")
+              .help("matches regardless of case"),
+          )
+          .arg(
+            Arg::with_name("regex")
+              .long("regex")
+              .short("e")
+              .help("reads the pattern as a regex"),
+          )
+          .arg(
+            Arg::with_name("before")
+              .long("before")
+              .short("B")
+              .takes_value(true)
+              .help("entries to show before each match"),
+          )
+          .arg(
+            Arg::with_name("after")
+              .long("after")
+              .short("A")
+              .takes_value(true)
+              .help("entries to show after each match"),
+          )
+          .arg(
+            Arg::with_name("context")
+              .long("context")
+              .short("C")
+              .takes_value(true)
+              .help("entries to show before and after each match"),
+          )
+          .arg(
+            Arg::with_name("no-color")
+              .long("no-color")
+              .help("doesn't highlight matches"),
+          )
+          .args(&Self::filter_args()),
+      )
+      .subcommand(
+        SubCommand::with_name("stats")
+          .about("shows journal statistics, optionally filtered")
+          .arg(
+            Arg::with_name("json")
+              .long("json")
+              .help("prints the statistics as json"),
+          )
+          .args(&Self::filter_args()),
+      )
+      .subcommand(
+        SubCommand::with_name("export")
+          .about("exports entries as json, json lines or csv, optionally filtered")
+          .arg(
+            Arg::with_name("format")
+              .long("format")
+              .short("f")
+              .takes_value(true)
+              .possible_values(&["json", "jsonl", "csv"])
+              .default_value("json")
+              .help("export format"),
+          )
+          .arg(
+            Arg::with_name("output")
+              .long("output")
+              .short("o")
+              .takes_value(true)
+              .help("file to write, stdout when missing"),
+          )
+          .args(&Self::filter_args())
+          .arg(Self::message_filter_arg()),
+      )
+      .subcommand(
+        SubCommand::with_name("import")
+          .about("imports entries from json, json lines or csv, keeping their ins")
+          .arg(Arg::with_name("file").required(true).help("file to read"))
+          .arg(
+            Arg::with_name("format")
+              .long("format")
+              .short("f")
+              .takes_value(true)
+              .possible_values(&["json", "jsonl", "csv"])
+              .help("import format, guessed from the file extension when missing"),
+          )
+          .arg(
+            Arg::with_name("dry-run")
+              .long("dry-run")
+              .help("shows what would be imported without writing"),
+          ),
+      )
+      .subcommand(
+        SubCommand::with_name("migrate")
+          .about("copies an existing journal file into the configured backend, keeping every ins")
+          .arg(
+            Arg::with_name("file")
+              .help("journal to copy, entries.log next to the configured journal when missing"),
+          )
+          .arg(
+            Arg::with_name("from")
+              .long("from")
+              .takes_value(true)
+              .possible_values(&["textfile", "jsonl"])
+              .default_value("textfile")
+              .help("format of the journal to copy"),
+          ),
+      )
+      .subcommand(
+        SubCommand::with_name("list")
+          .about("lists entries, optionally filtered")
+          .args(&Self::filter_args())
+          .arg(Self::message_filter_arg())
+          .arg(
+            Arg::with_name("last")
+              .long("last")
+              .short("n")
+              .takes_value(true)
+              .help(
+                "only the last <n> matching entries in journal order, stops reading once found",
+              ),
+          ),
+      )
+      .subcommand(
+        SubCommand::with_name("verify")
+          .about("checks the hash chain, offers to re-seal the journal when a link is broken")
+          .arg(
+            Arg::with_name("yes")
+              .long("yes")
+              .help("re-seals without asking when a link is broken"),
+          ),
+      );
+  }
+  fn arg_or_prompt(
+    &self,
+    matches: &ArgMatches,
+    name: &str,
+    read: fn(&Self) -> Result<String, String>,
+  ) -> Result<String, String> {
+    if let Some(value) = matches.value_of(name) {
+      return Ok(value.to_string());
+    }
+    if matches.is_present("no-input") {
+      return Err(format!(
+        "Missing --{} (prompting disabled by --no-input)",
+        name
+      ));
+    }
+    return read(self);
+  }
+  fn post(&self, matches: &ArgMatches) -> Result<EntryObject, String> {
+    let date =
+      self
+        .input_parsing
+        .parse_date(self.arg_or_prompt(matches, "date", Self::read_date)?);
+    let date = self.validate_date_input(date.as_str())?;
+    let time =
+      self
+        .input_parsing
+        .parse_time(self.arg_or_prompt(matches, "time", Self::read_time)?);
+    let time = self.validate_time_input(time.as_str())?;
+    let tag = match (matches.value_of("tag"), &self.configuration.default_tag) {
+      (None, Some(default_tag)) if matches.is_present("no-input") => default_tag.clone(),
+      _ => self.arg_or_prompt(matches, "tag", Self::read_tag)?,
+    };
+    let tag = self.validate_tag_input(tag.as_str())?;
+    let message = self.arg_or_prompt(matches, "message", Self::read_message)?;
+    let message = self.validate_message_input(message.as_str())?;
+    let entry_metadata = EntryMetadata {
+      date,
+      time,
+      ins: None,
+      tag,
+    };
+    let entry_object = EntryObject {
+      metadata: entry_metadata,
+      message,
+    };
+    return self.entry_controller.post_entry(entry_object);
+  }
+  fn add(&self, matches: &ArgMatches) -> Result<String, String> {
+    let entry_object = self.quick_add_parsing.parse(
+      &self.input_parsing,
+      matches.value_of("line").unwrap(),
+      self.configuration.default_tag.as_deref(),
+    )?;
+    self.entry_controller.post_entry(entry_object)?;
+    return Ok("Message written.".to_string());
+  }
+  fn confirm(&self, question: &str) -> Result<bool, String> {
+    print!("{} [y/N] > ", question);
+    std::io::stdout().flush().expect("flush failed");
+    let mut buf = String::new();
+    match std::io::stdin().read_line(&mut buf) {
+      Ok(_n) => {
+        let answer = Self::parse_new_line(buf).to_lowercase();
+        return Ok(answer == "y" || answer == "yes");
+      }
+      Err(_error) => return Err("couldn't read confirmation".to_string()),
+    }
+  }
+  fn delete(&self, matches: &ArgMatches) -> Result<String, String> {
+    let mut ins_list: Vec<String> = vec![];
+    for ins in matches.values_of("ins").unwrap() {
+      if !ins_list.iter().any(|item| item == ins) {
+        ins_list.push(ins.to_string());
+      }
+    }
+    let mut matching: Vec<EntryObject> = vec![];
+    for ins in &ins_list {
+      matching.push(self.entry_controller.get_entry(ins)?);
+    }
+    matching.sort();
+    for entry in &matching {
+      println!("{}", Self::format_entry(entry));
+    }
+    if !matches.is_present("yes")
+      && !self.confirm(format!("Delete {} entries?", matching.len()).as_str())?
+    {
+      return Ok("Nothing deleted.".to_string());
+    }
+    let deleted = self.entry_controller.delete_entries(&ins_list)?;
+    return Ok(format!(
+      "Deleted {} entries, the previous journal was kept as backup.",
+      deleted.len()
+    ));
+  }
+  fn edit(&self, matches: &ArgMatches) -> Result<String, String> {
+    let current = self
+      .entry_controller
+      .get_entry(matches.value_of("ins").unwrap())?;
+    let fields = ["date", "time", "tag", "message"];
+    let interactive = !fields.iter().any(|field| matches.is_present(field));
+    let field = |name: &str, value: &str| -> Result<String, String> {
+      if interactive {
+        return self.read_with_default(name, value);
+      }
+      return Ok(matches.value_of(name).unwrap_or(value).to_string());
+    };
+    let date = self
+      .input_parsing
+      .parse_date(field("date", current.metadata.date.as_str())?);
+    let date = self.validate_date_input(date.as_str())?;
+    let time = self
+      .input_parsing
+      .parse_time(field("time", current.metadata.time.as_str())?);
+    let time = self.validate_time_input(time.as_str())?;
+    let tag = self.validate_tag_input(field("tag", current.metadata.tag.as_str())?.as_str())?;
+    let message =
+      self.validate_message_input(field("message", current.message.as_str())?.as_str())?;
+    let entry_metadata = EntryMetadata {
+      ins: current.metadata.ins.clone(),
+      date,
+      time,
+      tag,
+    };
+    let entry_object = EntryObject {
+      metadata: entry_metadata,
+      message,
+    };
+    if entry_object == current {
+      return Ok("Nothing changed.".to_string());
+    }
+    self.entry_controller.update_entry(entry_object)?;
+    return Ok("Entry updated.".to_string());
+  }
+  fn filter_from_args(&self, matches: &ArgMatches) -> Result<EntryFilter, String> {
+    let mut filter = EntryFilter::new();
+    if let Some(from) = matches.value_of("from") {
+      filter.date_from = Some(self.parse_date_arg(from)?);
+    }
+    if let Some(to) = matches.value_of("to") {
+      filter.date_to = Some(self.parse_date_arg(to)?);
+    }
+    if let (Some(from), Some(to)) = (filter.date_from, filter.date_to) {
+      if from > to {
+        return Err("--from must not be after --to".to_string());
+      }
+    }
+    if let Some(tag) = matches.value_of("tag") {
+      filter.tag = Some(self.validate_tag_input(tag)?);
+    }
+    if let Some(time) = matches.value_of("time") {
+      let time = self.input_parsing.parse_time(time.to_string());
+      filter.time = Some(self.validate_time_input(time.as_str())?);
+    }
+    return Ok(filter);
+  }
+  fn export(&self, matches: &ArgMatches) -> Result<String, String> {
+    let format = ExchangeFormat::from_name(matches.value_of("format").unwrap())?;
+    let mut filter = self.filter_from_args(matches)?;
+    if let Some(message) = matches.value_of("message") {
+      filter.message = Some(message.to_string());
+    }
+    let entries = self.entry_controller.get_filtered_entries(&filter)?;
+    let content = EntryExchange::new(format).export(&entries)?;
+    match matches.value_of("output") {
+      Some(output) => {
+        std::fs::write(output, content)
+          .map_err(|err| format!("Couldn't write {}: {}", output, err))?;
+        return Ok(format!("Exported {} entries to {}.", entries.len(), output));
+      }
+      None => return Ok(content.trim_end_matches('\n').to_string()),
+    }
+  }
+  fn import(&self, matches: &ArgMatches) -> Result<String, String> {
+    let file = matches.value_of("file").unwrap();
+    let format_name = match matches.value_of("format") {
+      Some(format_name) => format_name,
+      None => std::path::Path::new(file)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or(""),
+    };
+    let format = ExchangeFormat::from_name(format_name)?;
+    let content =
+      std::fs::read_to_string(file).map_err(|err| format!("Couldn't read {}: {}", file, err))?;
+    let rows = EntryExchange::new(format).import(content.as_str())?;
+    let dry_run = matches.is_present("dry-run");
+    let report = self.entry_controller.import_entries(rows, dry_run)?;
+    let mut lines: Vec<String> = vec![];
+    for (line, reason) in &report.rejected {
+      lines.push(format!("Rejected line {}: {}", line, reason));
+    }
+    if dry_run {
+      for entry in &report.imported {
+        lines.push(Self::format_entry(entry));
+      }
+      lines.push(format!(
+        "Would import {} entries, {} rejected.",
+        report.imported.len(),
+        report.rejected.len()
+      ));
     } else {
-      return Err("No args".to_string());
+      lines.push(format!(
+        "Imported {} entries, {} rejected.",
+        report.imported.len(),
+        report.rejected.len()
+      ));
+    }
+    return Ok(lines.join("\n"));
+  }
+  // entries already present in the target are rejected like duplicates on import
+  fn migrate(&self, matches: &ArgMatches) -> Result<String, String> {
+    let from = matches.value_of("from").unwrap();
+    let file_system = &self.configuration.file_system;
+    let source = match matches.value_of("file") {
+      Some(file) => PathBuf::from(file),
+      None => {
+        let extension = if from == "jsonl" { ".jsonl" } else { ".log" };
+        Path::new(file_system.file_path.as_str())
+          .join(format!("{}{}", file_system.file_name, extension))
+      }
+    };
+    if !source.is_file() {
+      return Err(format!("Couldn't find {}", source.display()));
+    }
+    let config = FileSystemConfiguration {
+      file_path: match source.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.display().to_string(),
+        _ => ".".to_owned(),
+      },
+      file_name: source
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default(),
+      file_current_extension: source
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default(),
+      ..file_system.clone()
+    };
+    let persistence: Box<dyn EntryPersistenceInterface> = if from == "jsonl" {
+      Box::new(<JsonLinesPersistence as FileSystemLoader>::load(config))
+    } else {
+      Box::new(<FileSystemPersistence as FileSystemLoader>::load(config))
+    };
+    let entries = persistence.read_entries()?;
+    let mut lines: Vec<String> = vec![];
+    for diagnostic in persistence.take_diagnostics() {
+      lines.push(format!(
+        "Skipped line {}: {}: {}",
+        diagnostic.line, diagnostic.reason, diagnostic.raw
+      ));
+    }
+    let rows: Vec<ImportRow> = entries
+      .into_iter()
+      .enumerate()
+      .map(|(index, entry)| ImportRow {
+        line: index + 1,
+        entry: Ok(entry),
+      })
+      .collect();
+    let report = self.entry_controller.import_entries(rows, false)?;
+    for (entry, reason) in &report.rejected {
+      lines.push(format!("Rejected entry {}: {}", entry, reason));
+    }
+    lines.push(format!(
+      "Migrated {} entries from {}, {} rejected.",
+      report.imported.len(),
+      source.display(),
+      report.rejected.len()
+    ));
+    return Ok(lines.join("\n"));
+  }
+  fn list(&self, matches: &ArgMatches) -> Result<String, String> {
+    let mut filter = self.filter_from_args(matches)?;
+    if let Some(message) = matches.value_of("message") {
+      filter.message = Some(message.to_string());
+    }
+    let entries = match matches.value_of("last") {
+      Some(_last) => {
+        let count = Self::count_arg(matches, "last", 0)?;
+        self.entry_controller.get_latest_entries(&filter, count)?
+      }
+      None => self.entry_controller.get_filtered_entries(&filter)?,
+    };
+    let lines: Vec<String> = entries.iter().map(Self::format_entry).collect();
+    return Ok(lines.join("\n"));
+  }
+  fn count_arg(matches: &ArgMatches, name: &str, default: usize) -> Result<usize, String> {
+    match matches.value_of(name) {
+      Some(value) => {
+        return value
+          .parse::<usize>()
+          .map_err(|_err| format!("--{} expects a number", name))
+      }
+      None => return Ok(default),
+    }
+  }
+  fn search(&self, matches: &ArgMatches) -> Result<String, String> {
+    let search = EntrySearch::new(
+      matches.value_of("pattern").unwrap(),
+      matches.is_present("regex"),
+      matches.is_present("ignore-case"),
+    )
+    .map_err(|err| err.message())?;
+    let filter = self.filter_from_args(matches)?;
+    let context = Self::count_arg(matches, "context", 0)?;
+    let before = Self::count_arg(matches, "before", context)?;
+    let after = Self::count_arg(matches, "after", context)?;
+    let color = !matches.is_present("no-color");
+    let groups = self
+      .entry_controller
+      .search_entries(&search, &filter, before, after)?;
+    let blocks: Vec<String> = groups
+      .iter()
+      .map(|group| {
+        let lines: Vec<String> = group
+          .iter()
+          .map(|hit| Self::format_hit(hit, color))
+          .collect();
+        return lines.join("\n");
+      })
+      .collect();
+    if before == 0 && after == 0 {
+      return Ok(blocks.join("\n"));
+    }
+    return Ok(blocks.join("\n--\n"));
+  }
+  fn format_stats(stats: &EntryStats) -> String {
+    let mut lines: Vec<String> = vec![
+      format!("{:<16} {}", "total", stats.total),
+      format!(
+        "{:<16} {}",
+        "first date",
+        stats.first_date.as_deref().unwrap_or("-")
+      ),
+      format!(
+        "{:<16} {}",
+        "last date",
+        stats.last_date.as_deref().unwrap_or("-")
+      ),
+      format!("{:<16} {:.2}", "average per day", stats.average_per_day),
+    ];
+    let sections = vec![
+      ("per tag", &stats.per_tag),
+      ("per month", &stats.per_month),
+      ("per weekday", &stats.per_weekday),
+      ("per time", &stats.per_time),
+    ];
+    for (title, buckets) in sections {
+      lines.push(String::new());
+      lines.push(title.to_string());
+      for bucket in buckets {
+        lines.push(format!("  {:<14} {}", bucket.name, bucket.count));
+      }
+    }
+    return lines.join("\n");
+  }
+  fn stats(&self, matches: &ArgMatches) -> Result<String, String> {
+    let filter = self.filter_from_args(matches)?;
+    let stats = self.entry_controller.get_stats(&filter)?;
+    if matches.is_present("json") {
+      return serde_json::to_string_pretty(&stats).map_err(|err| err.to_string());
+    }
+    return Ok(Self::format_stats(&stats));
+  }
+  fn backups(&self, matches: &ArgMatches) -> Result<String, String> {
+    match matches.subcommand() {
+      ("list", Some(_list_matches)) => {
+        let lines: Vec<String> = self
+          .entry_controller
+          .list_backups()?
+          .iter()
+          .map(|backup| {
+            format!(
+              "{} {:>7} entries {:>10} bytes  {}",
+              backup.created.format("%Y-%m-%d %H:%M:%S"),
+              backup.entries,
+              backup.size,
+              backup.path
+            )
+          })
+          .collect();
+        if lines.is_empty() {
+          return Ok("No backups yet.".to_string());
+        }
+        return Ok(lines.join("\n"));
+      }
+      _ => return Err("Expected a backups subcommand: list".to_string()),
+    }
+  }
+  fn format_chain_report(report: &ChainReport) -> String {
+    match &report.first_broken {
+      Some(broken) => {
+        return format!(
+          "{}: broken at line {}: {}\n  {}",
+          report.path, broken.line, broken.reason, broken.raw
+        )
+      }
+      None => {
+        return format!(
+          "{}: {} lines intact, last hash {}",
+          report.path,
+          report.lines,
+          report.last_hash.as_deref().unwrap_or("none")
+        )
+      }
+    }
+  }
+  fn verify(&self, matches: &ArgMatches) -> Result<String, String> {
+    let reports = self.entry_controller.verify_chain()?;
+    let output: Vec<String> = reports.iter().map(Self::format_chain_report).collect();
+    if reports.iter().all(|report| report.first_broken.is_none()) {
+      return Ok(output.join("\n"));
+    }
+    println!("{}", output.join("\n"));
+    if !self.configuration.file_system.hash_chain {
+      return Ok("Set hash_chain = true to seal the journal.".to_string());
+    }
+    if !matches.is_present("yes")
+      && !self.confirm("Re-seal the journal as it is now, accepting every line?")?
+    {
+      return Ok("Nothing re-sealed.".to_string());
+    }
+    let count = self.entry_controller.reseal_chain()?;
+    return Ok(format!(
+      "Re-sealed {} lines, the previous journal was kept as backup.",
+      count
+    ));
+  }
+  // needs no journal, runs before it is unlocked and opened
+  pub fn config(configuration: &Configuration, matches: &ArgMatches) -> Result<String, String> {
+    match matches.subcommand() {
+      ("show", Some(_show_matches)) => return configuration.show(),
+      _ => return Err("Expected a config subcommand: show".to_string()),
+    }
+  }
+  pub fn listen(&self, matches: &ArgMatches) -> Result<String, String> {
+    if matches.is_present("post") {
+      self.post(matches)?;
+      return Ok("Message written.".to_string());
+    }
+    match matches.subcommand() {
+      ("add", Some(add_matches)) => return self.add(add_matches),
+      ("backups", Some(backups_matches)) => return self.backups(backups_matches),
+      ("compact", Some(compact_matches)) => {
+        let count = self.entry_controller.compact()?;
+        if !compact_matches.is_present("archive") {
+          return Ok(format!("Compacted {} entries.", count));
+        }
+        let archived = self.entry_controller.archive()?;
+        return Ok(format!(
+          "Compacted {} entries, archived {} files.",
+          count,
+          archived.len()
+        ));
+      }
+      ("config", Some(config_matches)) => return Self::config(&self.configuration, config_matches),
+      ("delete", Some(delete_matches)) => return self.delete(delete_matches),
+      ("edit", Some(edit_matches)) => return self.edit(edit_matches),
+      ("export", Some(export_matches)) => return self.export(export_matches),
+      ("import", Some(import_matches)) => return self.import(import_matches),
+      ("list", Some(list_matches)) => return self.list(list_matches),
+      ("migrate", Some(migrate_matches)) => return self.migrate(migrate_matches),
+      ("search", Some(search_matches)) => return self.search(search_matches),
+      ("stats", Some(stats_matches)) => return self.stats(stats_matches),
+      ("verify", Some(verify_matches)) => return self.verify(verify_matches),
+      _ => return Err("No args".to_string()),
     }
   }
 }
 
 /* -----------------------------------TESTS------------------------------------------ */
-pub struct TestFileSystemPersistence {}
-impl EntryPersistenceInterface for TestFileSystemPersistence {
-  fn read_entries(&self) -> Result<Vec<EntryObject>, String> {
-    let mut mock_vec = vec![];
-    for _i in 0..36500 {
-      let mock_metadata = EntryMetadata {
-        date: "10-jan-20".to_string(),
-        time: "morning".to_string(),
-        ins: Some("0000000000000".to_string()),
-        tag: "mock metadata".to_string(),
-      };
-      let mock_object = EntryObject {
-        metadata: mock_metadata,
-        message: "mock message".to_string(),
-      };
-      mock_vec.push(mock_object);
-    }
-    return Ok(mock_vec);
-  }
-  fn write_entry(&self, entry: EntryObject) -> Result<EntryObject, String> {
-    return Ok(entry);
+#[cfg(test)]
+mod tests {
+  use super::super::persistence_memory::MemoryPersistence;
+  use super::super::test_support::{test_dir, test_entry_with};
+  use super::*;
+  use std::rc::Rc;
+  fn test_cli(entries: Vec<EntryObject>) -> CliInput {
+    let controller = EntryController::new(
+      Rc::new(EntryBusiness {}),
+      Rc::new(MemoryPersistence::with_entries(entries)),
+    );
+    return CliInput::new(controller, Configuration::default());
+  }
+  #[test]
+  fn listen_test() {
+    let cli = test_cli(vec![
+      test_entry_with("0000000000002", "13-oct-20", "work", "report"),
+      test_entry_with("0000000000001", "13-oct-20", "fit", "run"),
+    ]);
+    let listen = |args: Vec<&str>| -> Result<String, String> {
+      let mut full_args = vec!["entrylogger"];
+      full_args.extend(args);
+      return cli.listen(&CliInput::app().get_matches_from(full_args));
+    };
+    assert_eq!(
+      listen(vec!["list", "--tag", "fit"]).unwrap(),
+      "0000000000001 13-oct-20 morning   fit          run"
+    );
+    listen(vec!["add", "14-oct-20 night #fit swim"]).unwrap();
+    assert_eq!(
+      listen(vec!["list", "--tag", "fit"])
+        .unwrap()
+        .lines()
+        .count(),
+      2
+    );
+    assert!(listen(vec!["delete", "0000000000002", "--yes"]).is_ok());
+    assert_eq!(
+      listen(vec!["list", "--tag", "work"]).unwrap(),
+      "".to_string()
+    );
+    assert!(listen(vec!["delete", "0000000000002", "--yes"]).is_err());
+    // the controller rejects an invalid entry instead of writing it
+    assert_eq!(
+      cli
+        .entry_controller
+        .post_entry(test_entry_with("0000000000003", "13-oct-20", "Fit", "run"))
+        .map(|_entry| ()),
+      Err("Only lowercase alphanumerical characters allowed in tag".to_string())
+    );
+    assert_eq!(cli.entry_controller.get_entries().unwrap().len(), 2);
+  }
+  #[test]
+  fn listen_post_test() {
+    let cli = test_cli(vec![]);
+    let listen = |args: Vec<&str>| -> Result<String, String> {
+      let mut full_args = vec!["entrylogger", "--post", "--no-input"];
+      full_args.extend(args);
+      return cli.listen(&CliInput::app().get_matches_from(full_args));
+    };
+    assert_eq!(
+      listen(vec![
+        "--date",
+        "13-oct-20",
+        "--time",
+        "morning",
+        "--tag",
+        "fit",
+        "--message",
+        "run"
+      ]),
+      Ok("Message written.".to_string())
+    );
+    let entries = cli.entry_controller.get_entries().unwrap();
+    assert_eq!(
+      entries,
+      vec![test_entry_with(
+        entries[0].metadata.ins.as_ref().unwrap(),
+        "13-oct-20",
+        "fit",
+        "run"
+      )]
+    );
+    // nothing is written when a field is missing
+    assert_eq!(
+      listen(vec![
+        "--date",
+        "13-oct-20",
+        "--tag",
+        "fit",
+        "--message",
+        "run"
+      ]),
+      Err("Missing --time (prompting disabled by --no-input)".to_string())
+    );
+    assert_eq!(cli.entry_controller.get_entries().unwrap().len(), 1);
+    // today and now are resolved before the entry is written
+    listen(vec![
+      "--date",
+      "today",
+      "--time",
+      "now",
+      "--tag",
+      "fit",
+      "--message",
+      "walk",
+    ])
+    .unwrap();
+    let input_parsing = InputParsing {};
+    let walk = cli
+      .entry_controller
+      .get_entries()
+      .unwrap()
+      .into_iter()
+      .find(|entry| entry.message == "walk")
+      .unwrap();
+    assert_eq!(
+      walk.metadata.date,
+      input_parsing.parse_date("today".to_string())
+    );
+    assert_eq!(
+      walk.metadata.time,
+      input_parsing.parse_time("now".to_string())
+    );
+  }
+  #[test]
+  fn listen_import_test() {
+    let cli = test_cli(vec![]);
+    let file = test_dir("import").join("entries.jsonl");
+    std::fs::write(
+      &file,
+      "{\"ins\":\"0000000000001\",\"date\":\"13-Oct-20\",\"time\":\"morning\",\"tag\":\"fit\",\"message\":\"run\"}\n",
+    )
+    .unwrap();
+    assert_eq!(
+      cli.listen(&CliInput::app().get_matches_from(vec![
+        "entrylogger",
+        "import",
+        file.to_str().unwrap()
+      ])),
+      Ok("Imported 1 entries, 0 rejected.".to_string())
+    );
+    assert_eq!(
+      cli.entry_controller.get_entries().unwrap(),
+      vec![test_entry_with("0000000000001", "13-oct-20", "fit", "run")]
+    );
+  }
+  #[test]
+  fn quick_add_parse_test() {
+    let input_parsing = InputParsing {};
+    let parser = QuickAddParsing {};
+    let object = parser
+      .parse(
+        &input_parsing,
+        "13-oct-20 morning #fit aerobic  (5/5) ",
+        None,
+      )
+      .unwrap();
+    let compare_metadata = EntryMetadata {
+      ins: None,
+      date: "13-oct-20".to_string(),
+      time: "morning".to_string(),
+      tag: "fit".to_string(),
+    };
+    let compare_object = EntryObject {
+      metadata: compare_metadata,
+      message: "aerobic  (5/5)".to_string(),
+    };
+    assert_eq!(object, compare_object);
+
+    let defaults = parser
+      .parse(&input_parsing, "#fit night run", None)
+      .unwrap();
+    assert_eq!(
+      defaults.metadata.date,
+      input_parsing.parse_date("today".to_string())
+    );
+    assert_eq!(
+      defaults.metadata.time,
+      input_parsing.parse_time("now".to_string())
+    );
+    assert_eq!(defaults.message, "night run");
+
+    let yesterday = parser
+      .parse(&input_parsing, "n/a yesterday #fit run", None)
+      .unwrap();
+    assert_eq!(
+      yesterday.metadata.date,
+      input_parsing.parse_date("yesterday".to_string())
+    );
+    assert_eq!(yesterday.metadata.time, "n/a");
+
+    let default_tag = parser
+      .parse(&input_parsing, "night run #gym", Some("fit"))
+      .unwrap_err();
+    assert_eq!(default_tag, "Tag '#gym' must come before the message");
+    let default_tag = parser
+      .parse(&input_parsing, "night run", Some("fit"))
+      .unwrap();
+    assert_eq!(default_tag.metadata.tag, "fit");
+    assert_eq!(default_tag.message, "run");
+  }
+  #[test]
+  fn quick_add_parse_error_test() {
+    let input_parsing = InputParsing {};
+    let parser = QuickAddParsing {};
+    let errors = vec![
+      ("", "Missing #tag"),
+      (
+        "today night run",
+        "Expected a date, a time or a #tag before the message, found 'run'",
+      ),
+      (
+        "today yesterday #fit run",
+        "Date given twice: 'today' and 'yesterday'",
+      ),
+      ("now night #fit run", "Time given twice: 'now' and 'night'"),
+      (
+        "#fit #gym run",
+        "Only one tag allowed, found '#fit' and '#gym'",
+      ),
+      ("# run", "Empty tag after '#'"),
+      ("#fit", "Missing message after '#fit'"),
+      ("#fit run #gym", "Tag '#gym' must come before the message"),
+    ];
+    for (line, message) in errors {
+      assert_eq!(
+        parser.parse(&input_parsing, line, None),
+        Err(message.to_string())
+      );
+    }
   }
 }
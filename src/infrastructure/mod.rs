@@ -1,30 +1,149 @@
 use std::rc::*;
 
+pub mod configuration;
+pub mod encryption;
+pub mod entry_index;
+pub mod exchange_formats;
+pub mod file_lock;
 pub mod interface_cli;
+pub mod persistence_jsonlines;
+pub mod persistence_memory;
+pub mod persistence_sharded;
+pub mod persistence_sqlite;
 pub mod persistence_textfile;
+#[cfg(test)]
+mod test_support;
 
 use application::*;
 use domain::*;
 
+// the file handling of the textfile and jsonl backends, also used for every shard
+fn file_loader(
+  backend: &str,
+) -> fn(persistence_textfile::FileSystemConfiguration) -> persistence_textfile::FileSystemPersistence
+{
+  match backend {
+    "jsonl" => return persistence_jsonlines::JsonLinesPersistence::files,
+    _ => return <persistence_textfile::FileSystemPersistence as persistence_textfile::FileSystemLoader>::load,
+  }
+}
+
+// the key is derived once and travels with the file system configuration to every file
+fn unlock_journal(configuration: &mut configuration::Configuration) -> Result<(), String> {
+  if let Some(source) = configuration.encryption {
+    let encrypt_plaintext = |key: &encryption::EncryptionKey| -> Result<usize, String> {
+      let config = persistence_textfile::FileSystemConfiguration {
+        encryption_key: Some(key.clone()),
+        ..configuration.file_system.clone()
+      };
+      let loader = file_loader(configuration.backend.as_str());
+      match config.shard_by {
+        Some(period) => {
+          return persistence_sharded::ShardedPersistence::new(config, period, loader)
+            .encrypt_plaintext()
+        }
+        None => return loader(config).encrypt_plaintext(),
+      }
+    };
+    let key = encryption::unlock(
+      &configuration.file_system,
+      source,
+      configuration.encryption_key_file.as_deref(),
+      &encrypt_plaintext,
+    )?;
+    configuration.file_system.encryption_key = Some(key);
+  }
+  return Ok(());
+}
+
+fn load_persistence(
+  configuration: &configuration::Configuration,
+) -> Result<Rc<dyn EntryPersistenceInterface>, String> {
+  if let Some(period) = configuration.file_system.shard_by {
+    let loader = file_loader(configuration.backend.as_str());
+    let persistence = persistence_sharded::ShardedPersistence::new(
+      configuration.file_system.clone(),
+      period,
+      loader,
+    );
+    for message in persistence.recover()? {
+      eprintln!("Recovery: {}", message);
+    }
+    return Ok(Rc::new(persistence));
+  }
+  match configuration.backend.as_str() {
+    "textfile" => {
+      let persistence: persistence_textfile::FileSystemPersistence =
+        persistence_textfile::FileSystemLoader::load(configuration.file_system.clone());
+      for message in persistence.recover()? {
+        eprintln!("Recovery: {}", message);
+      }
+      return Ok(Rc::new(persistence));
+    }
+    "jsonl" => {
+      let persistence: persistence_jsonlines::JsonLinesPersistence =
+        persistence_textfile::FileSystemLoader::load(configuration.file_system.clone());
+      for message in persistence.recover()? {
+        eprintln!("Recovery: {}", message);
+      }
+      return Ok(Rc::new(persistence));
+    }
+    "sqlite" => {
+      let persistence = persistence_sqlite::SqlitePersistence::open(&configuration.file_system)?;
+      return Ok(Rc::new(persistence));
+    }
+    // nothing outlives the run, for trying out commands
+    "memory" => return Ok(Rc::new(persistence_memory::MemoryPersistence::default())),
+    backend => return Err(format!("Unknown backend {}", backend)),
+  }
+}
+
+// scripts tell a failed command apart by its exit status
+fn exit_with_error(err: String) -> ! {
+  eprintln!("Error: {}", err);
+  std::process::exit(1);
+}
+
 pub fn start_entrylogger_cli_fs() {
-  let config = persistence_textfile::FileSystemConfiguration {
-    file_name: "entries".to_owned(),
-    file_path: "./".to_owned(),
-    file_current_extension: ".log".to_owned(),
-    file_backup_extension: ".bak".to_owned(),
+  let matches = interface_cli::CliInput::app().get_matches();
+  let mut configuration = match configuration::Configuration::load(&matches) {
+    Ok(configuration) => configuration,
+    Err(err) => exit_with_error(err),
   };
-
-  let persistence: persistence_textfile::FileSystemPersistence =
-    persistence_textfile::FileSystemLoader::load(config);
-  let business = EntryBusiness {};
-  let controller = EntryController::new(Rc::new(business), Rc::new(persistence));
-  let cli_input = interface_cli::CliInput::new(controller);
-  match cli_input.listen() {
-    Ok(_val) => {
-      println!("Message written.");
+  // the journal is only unlocked for commands that open it
+  if let ("config", Some(config_matches)) = matches.subcommand() {
+    match interface_cli::CliInput::config(&configuration, config_matches) {
+      Ok(output) => println!("{}", output),
+      Err(err) => exit_with_error(err),
     }
-    Err(err) => {
-      println!("Error: {}", err);
+    return;
+  }
+  if let Err(err) = unlock_journal(&mut configuration) {
+    exit_with_error(err);
+  }
+  let persistence = match load_persistence(&configuration) {
+    Ok(persistence) => persistence,
+    Err(err) => exit_with_error(err),
+  };
+  let business = EntryBusiness {};
+  let controller = EntryController::new(Rc::new(business), persistence.clone());
+  let cli_input = interface_cli::CliInput::new(controller, configuration);
+  let result = cli_input.listen(&matches);
+  for message in persistence.take_recovery() {
+    eprintln!("Recovery: {}", message);
+  }
+  for diagnostic in persistence.take_diagnostics() {
+    eprintln!(
+      "Skipped line {}: {}: {}",
+      diagnostic.line, diagnostic.reason, diagnostic.raw
+    );
+  }
+  match result {
+    Ok(output) => {
+      if !output.is_empty() {
+        println!("{}", output);
+      }
     }
+    Err(err) => exit_with_error(err),
   }
 }
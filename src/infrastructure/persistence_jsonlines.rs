@@ -0,0 +1,137 @@
+use application::*;
+use domain::*;
+
+use super::exchange_formats::EntryRecord;
+use super::persistence_textfile::*;
+
+// one json object per line, readable with jq and the other line based tools
+pub struct JsonLineConverter {}
+
+impl EntryStringConverter for JsonLineConverter {
+  fn parse_string(&self, entry_string: String) -> Result<EntryObject, String> {
+    let entry = serde_json::from_str::<EntryRecord>(entry_string.as_str())
+      .map(EntryRecord::into_entry)
+      .map_err(|err| format!("Invalid json: {}", err))?;
+    check_sortable(&entry.metadata)?;
+    return Ok(entry);
+  }
+  fn serialize_entry(&self, entry: EntryObject) -> Result<String, String> {
+    return serde_json::to_string(&EntryRecord::from_entry(&entry)).map_err(|err| err.to_string());
+  }
+}
+
+// locking, appends, atomic rewrites and backups are shared with the text file
+pub struct JsonLinesPersistence {
+  files: FileSystemPersistence,
+}
+
+impl EntryPersistenceInterface for JsonLinesPersistence {
+  fn read_entries(&self) -> Result<Vec<EntryObject>, String> {
+    return self.files.read_entries();
+  }
+  fn query_entries(&self, filter: &EntryFilter) -> Result<Vec<EntryObject>, String> {
+    return self.files.query_entries(filter);
+  }
+  fn find_entry(&self, ins: &str) -> Result<Option<EntryObject>, String> {
+    return self.files.find_entry(ins);
+  }
+  fn for_each_entry(
+    &self,
+    reverse: bool,
+    visit: &mut dyn FnMut(EntryObject) -> bool,
+  ) -> Result<(), String> {
+    return self.files.for_each_entry(reverse, visit);
+  }
+  fn write_entry(&self, entry: EntryObject) -> Result<EntryObject, String> {
+    return self.files.write_entry(entry);
+  }
+  fn insert_entry(&self, entry: EntryObject) -> Result<EntryObject, String> {
+    return self.files.insert_entry(entry);
+  }
+  fn write_entries(&self, entries: Vec<EntryObject>) -> Result<Vec<EntryObject>, String> {
+    return self.files.write_entries(entries);
+  }
+  fn update_entry(&self, entry: EntryObject) -> Result<EntryObject, String> {
+    return self.files.update_entry(entry);
+  }
+  fn delete_entries(&self, ins_list: &[String]) -> Result<Vec<EntryObject>, String> {
+    return self.files.delete_entries(ins_list);
+  }
+  fn compact(&self) -> Result<usize, String> {
+    return self.files.compact();
+  }
+  fn take_diagnostics(&self) -> Vec<LoadDiagnostic> {
+    return self.files.take_diagnostics();
+  }
+  fn list_backups(&self) -> Result<Vec<BackupInfo>, String> {
+    return self.files.list_backups();
+  }
+  fn archive(&self) -> Result<Vec<String>, String> {
+    return self.files.archive();
+  }
+  fn verify_chain(&self) -> Result<Vec<ChainReport>, String> {
+    return self.files.verify_chain();
+  }
+  fn reseal_chain(&self) -> Result<usize, String> {
+    return self.files.reseal_chain();
+  }
+}
+
+impl JsonLinesPersistence {
+  // the shared file handling with json lines, also used for every shard of a sharded journal
+  pub fn files(config: FileSystemConfiguration) -> FileSystemPersistence {
+    return FileSystemPersistence::with_converter(config, Box::new(JsonLineConverter {}));
+  }
+  pub fn recover(&self) -> Result<Vec<String>, String> {
+    return self.files.recover();
+  }
+}
+
+impl FileSystemLoader for JsonLinesPersistence {
+  fn load(config: FileSystemConfiguration) -> Self {
+    return JsonLinesPersistence {
+      files: JsonLinesPersistence::files(config),
+    };
+  }
+}
+
+/* -----------------------------------TESTS------------------------------------------ */
+#[cfg(test)]
+mod tests {
+  use super::super::test_support::{test_dir, test_entry};
+  use super::*;
+  #[test]
+  fn json_lines_persistence_test() {
+    let dir = test_dir("jsonl");
+    let config = FileSystemConfiguration {
+      file_path: dir.to_str().unwrap().to_owned(),
+      file_current_extension: ".jsonl".to_owned(),
+      ..FileSystemConfiguration::default()
+    };
+    let persistence: JsonLinesPersistence = FileSystemLoader::load(config);
+    persistence
+      .write_entry(test_entry("0000000000002", "two [2]\nlines"))
+      .unwrap();
+    persistence
+      .write_entry(test_entry("0000000000001", "one"))
+      .unwrap();
+    let content = std::fs::read_to_string(dir.join("entries.jsonl")).unwrap();
+    assert_eq!(
+      content.lines().nth(1).unwrap(),
+      "{\"ins\":\"0000000000001\",\"date\":\"13-oct-20\",\"time\":\"morning\",\"tag\":\"fit\",\"message\":\"one\"}"
+    );
+    persistence
+      .delete_entries(&["0000000000001".to_string()])
+      .unwrap();
+    assert_eq!(
+      persistence.read_entries().unwrap(),
+      vec![test_entry("0000000000002", "two [2]\nlines")]
+    );
+    // the backup keeps the file as it was before the rewrite
+    let backups = persistence.list_backups().unwrap();
+    let backup = std::fs::read_to_string(&backups[0].path).unwrap();
+    assert_eq!(backup, content);
+    std::fs::write(dir.join("entries.jsonl"), "{\"ins\":\"x\"}\n").unwrap();
+    assert!(persistence.read_entries().is_err());
+  }
+}
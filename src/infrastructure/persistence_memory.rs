@@ -0,0 +1,166 @@
+use std::cell::RefCell;
+
+use application::*;
+use domain::*;
+
+// keeps entries sorted in memory, for tests and runs that shouldn't touch the filesystem
+#[derive(Default)]
+pub struct MemoryPersistence {
+  entries: RefCell<Vec<EntryObject>>,
+}
+
+impl MemoryPersistence {
+  // seeds the list for tests
+  #[cfg(test)]
+  pub fn with_entries(mut entries: Vec<EntryObject>) -> MemoryPersistence {
+    entries.sort();
+    return MemoryPersistence {
+      entries: RefCell::new(entries),
+    };
+  }
+  fn insert(&self, entry: EntryObject) {
+    let mut entries = self.entries.borrow_mut();
+    let index = match entries.binary_search(&entry) {
+      Ok(index) => index,
+      Err(index) => index,
+    };
+    entries.insert(index, entry);
+  }
+}
+
+impl EntryPersistenceInterface for MemoryPersistence {
+  fn read_entries(&self) -> Result<Vec<EntryObject>, String> {
+    return Ok(self.entries.borrow().clone());
+  }
+  fn write_entry(&self, entry: EntryObject) -> Result<EntryObject, String> {
+    self.insert(entry.clone());
+    return Ok(entry);
+  }
+  fn write_entries(&self, entries: Vec<EntryObject>) -> Result<Vec<EntryObject>, String> {
+    for entry in &entries {
+      self.insert(entry.clone());
+    }
+    return Ok(entries);
+  }
+  fn update_entry(&self, entry: EntryObject) -> Result<EntryObject, String> {
+    let position = self
+      .entries
+      .borrow()
+      .iter()
+      .position(|item| item.metadata.ins == entry.metadata.ins);
+    match position {
+      Some(index) => {
+        self.entries.borrow_mut().remove(index);
+        self.insert(entry.clone());
+        return Ok(entry);
+      }
+      None => {
+        return Err(format!(
+          "No entry found with ins {}",
+          entry.metadata.ins.unwrap_or_default()
+        ))
+      }
+    }
+  }
+  fn delete_entries(&self, ins_list: &[String]) -> Result<Vec<EntryObject>, String> {
+    let mut entries = self.entries.borrow_mut();
+    for ins in ins_list {
+      if !entries
+        .iter()
+        .any(|item| item.metadata.ins.as_ref() == Some(ins))
+      {
+        return Err(format!("No entry found with ins {}", ins));
+      }
+    }
+    let (deleted, kept): (Vec<EntryObject>, Vec<EntryObject>) =
+      entries
+        .drain(..)
+        .partition(|item| match &item.metadata.ins {
+          Some(ins) => ins_list.contains(ins),
+          None => false,
+        });
+    *entries = kept;
+    return Ok(deleted);
+  }
+  // already sorted, there is nothing to rewrite
+  fn compact(&self) -> Result<usize, String> {
+    return Ok(self.entries.borrow().len());
+  }
+  fn take_diagnostics(&self) -> Vec<LoadDiagnostic> {
+    return vec![];
+  }
+}
+
+/* -----------------------------------TESTS------------------------------------------ */
+#[cfg(test)]
+mod tests {
+  use super::super::test_support::test_entry_with;
+  use super::*;
+  #[test]
+  fn memory_persistence_test() {
+    let persistence = MemoryPersistence::with_entries(vec![test_entry_with(
+      "0000000000003",
+      "01-jan-21",
+      "fit",
+      "three",
+    )]);
+    persistence
+      .write_entry(test_entry_with("0000000000002", "13-oct-20", "fit", "two"))
+      .unwrap();
+    persistence
+      .write_entries(vec![test_entry_with(
+        "0000000000001",
+        "14-oct-20",
+        "fit",
+        "one",
+      )])
+      .unwrap();
+    let ins_order = |persistence: &MemoryPersistence| -> Vec<String> {
+      return persistence
+        .read_entries()
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.metadata.ins.unwrap())
+        .collect();
+    };
+    assert_eq!(
+      ins_order(&persistence),
+      vec!["0000000000002", "0000000000001", "0000000000003"]
+    );
+    // moving an entry to another date moves it in the order too
+    persistence
+      .update_entry(test_entry_with(
+        "0000000000003",
+        "12-oct-20",
+        "fit",
+        "three",
+      ))
+      .unwrap();
+    assert_eq!(
+      ins_order(&persistence),
+      vec!["0000000000003", "0000000000002", "0000000000001"]
+    );
+    assert!(persistence
+      .update_entry(test_entry_with("0000000000009", "12-oct-20", "fit", "nine"))
+      .is_err());
+    assert!(persistence
+      .delete_entries(&["0000000000002".to_string(), "0000000000009".to_string()])
+      .is_err());
+    assert_eq!(
+      persistence
+        .delete_entries(&["0000000000002".to_string()])
+        .unwrap(),
+      vec![test_entry_with("0000000000002", "13-oct-20", "fit", "two")]
+    );
+    assert_eq!(persistence.compact().unwrap(), 2);
+    // 0000000000001 comes last, so a post gets the ins after it
+    assert_eq!(
+      persistence
+        .insert_entry(test_entry_with("0000000000001", "15-oct-20", "fit", "four"))
+        .unwrap()
+        .metadata
+        .ins,
+      Some("0000000000002".to_string())
+    );
+  }
+}
@@ -0,0 +1,595 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{read_dir, rename};
+use std::path::Path;
+
+use chrono::{Datelike, Local, NaiveDate};
+
+use application::*;
+use domain::*;
+
+use super::file_lock::FileLock;
+use super::persistence_textfile::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShardPeriod {
+  Month,
+  Year,
+}
+
+impl ShardPeriod {
+  fn key(&self, date: NaiveDate) -> String {
+    match self {
+      ShardPeriod::Month => return date.format("%Y-%m").to_string(),
+      ShardPeriod::Year => return date.format("%Y").to_string(),
+    }
+  }
+  // first and last day a shard covers, None for anything that isn't a shard key
+  fn range(&self, key: &str) -> Option<(NaiveDate, NaiveDate)> {
+    match self {
+      ShardPeriod::Month => {
+        if key.len() != 7 {
+          return None;
+        }
+        let first = NaiveDate::parse_from_str(format!("{}-01", key).as_str(), "%Y-%m-%d").ok()?;
+        let next = if first.month() == 12 {
+          NaiveDate::from_ymd_opt(first.year() + 1, 1, 1)?
+        } else {
+          NaiveDate::from_ymd_opt(first.year(), first.month() + 1, 1)?
+        };
+        return Some((first, next.pred_opt()?));
+      }
+      ShardPeriod::Year => {
+        if key.len() != 4 || !key.chars().all(|character| character.is_ascii_digit()) {
+          return None;
+        }
+        let year = key.parse::<i32>().ok()?;
+        return Some((
+          NaiveDate::from_ymd_opt(year, 1, 1)?,
+          NaiveDate::from_ymd_opt(year, 12, 31)?,
+        ));
+      }
+    }
+  }
+}
+
+// one journal file per month or year, e.g. entries-2024-02.log, each with its own backups
+pub struct ShardedPersistence {
+  config: FileSystemConfiguration,
+  period: ShardPeriod,
+  loader: fn(FileSystemConfiguration) -> FileSystemPersistence,
+  diagnostics: RefCell<Vec<LoadDiagnostic>>,
+  // shards already checked by this process, and what repairing them did
+  recovered: RefCell<BTreeSet<String>>,
+  recovery: RefCell<Vec<String>>,
+}
+
+impl EntryPersistenceInterface for ShardedPersistence {
+  fn read_entries(&self) -> Result<Vec<EntryObject>, String> {
+    let _lock = self.lock(false)?;
+    let keys = self.shard_keys()?;
+    return self.read_shards(&keys);
+  }
+  // only opens the shards that overlap the date range
+  fn query_entries(&self, filter: &EntryFilter) -> Result<Vec<EntryObject>, String> {
+    let _lock = self.lock(false)?;
+    let keys: Vec<String> = self
+      .shard_keys()?
+      .into_iter()
+      .filter(|key| {
+        let (first, last) = self.period.range(key).unwrap();
+        return filter.date_from.is_none_or(|from| last >= from)
+          && filter.date_to.is_none_or(|to| first <= to);
+      })
+      .collect();
+    let mut entries: Vec<EntryObject> = vec![];
+    for key in keys {
+      let shard = self.shard(key.as_str())?;
+      let result = shard.query_entries(filter);
+      self.collect_diagnostics(&shard, key.as_str());
+      entries.extend(result?);
+    }
+    entries.sort();
+    return Ok(entries);
+  }
+  fn find_entry(&self, ins: &str) -> Result<Option<EntryObject>, String> {
+    let _lock = self.lock(false)?;
+    for key in self.shard_keys()? {
+      let shard = self.shard(key.as_str())?;
+      let result = shard.find_entry(ins);
+      self.collect_diagnostics(&shard, key.as_str());
+      if let Some(entry) = result? {
+        return Ok(Some(entry));
+      }
+    }
+    return Ok(None);
+  }
+  // shards in date order, the last period first when reversed
+  fn for_each_entry(
+    &self,
+    reverse: bool,
+    visit: &mut dyn FnMut(EntryObject) -> bool,
+  ) -> Result<(), String> {
+    let _lock = self.lock(false)?;
+    let mut keys = self.shard_keys()?;
+    if reverse {
+      keys.reverse();
+    }
+    let mut stopped = false;
+    for key in keys {
+      let shard = self.shard(key.as_str())?;
+      let result = shard.for_each_entry(reverse, &mut |entry| {
+        stopped = !visit(entry);
+        return !stopped;
+      });
+      self.collect_diagnostics(&shard, key.as_str());
+      result?;
+      if stopped {
+        break;
+      }
+    }
+    return Ok(());
+  }
+  fn write_entry(&self, entry: EntryObject) -> Result<EntryObject, String> {
+    self.write_entries(vec![entry.clone()])?;
+    return Ok(entry);
+  }
+  // free in every shard, past the last one in the shard it goes to
+  fn insert_entry(&self, mut entry: EntryObject) -> Result<EntryObject, String> {
+    let _lock = self.lock(true)?;
+    let key = self.shard_key_for(&entry)?;
+    let shard = self.shard(key.as_str())?;
+    let mut last: Option<String> = None;
+    let result = shard.for_each_entry(true, &mut |stored| {
+      last = stored.metadata.ins;
+      return false;
+    });
+    self.collect_diagnostics(&shard, key.as_str());
+    result?;
+    let mut ins = ins_past(entry.metadata.ins.unwrap_or_default(), last)?;
+    while !self.find_shards(std::slice::from_ref(&ins))?.is_empty() {
+      ins = next_ins(ins.as_str())?;
+    }
+    entry.metadata.ins = Some(ins);
+    return shard.write_entry(entry);
+  }
+  fn write_entries(&self, entries: Vec<EntryObject>) -> Result<Vec<EntryObject>, String> {
+    let _lock = self.lock(true)?;
+    let mut groups: BTreeMap<String, Vec<EntryObject>> = BTreeMap::new();
+    for entry in &entries {
+      groups
+        .entry(self.shard_key_for(entry)?)
+        .or_default()
+        .push(entry.clone());
+    }
+    for (key, group) in groups {
+      self.shard(key.as_str())?.write_entries(group)?;
+    }
+    return Ok(entries);
+  }
+  // an entry moved to another period is written to its new shard before it leaves the old one
+  fn update_entry(&self, entry: EntryObject) -> Result<EntryObject, String> {
+    let _lock = self.lock(true)?;
+    let ins = entry.metadata.ins.clone().unwrap_or_default();
+    let old_key = match self.find_shards(std::slice::from_ref(&ins))?.remove(&ins) {
+      Some(key) => key,
+      None => return Err(format!("No entry found with ins {}", ins)),
+    };
+    let new_key = self.shard_key_for(&entry)?;
+    if new_key == old_key {
+      return self.shard(old_key.as_str())?.update_entry(entry);
+    }
+    self.shard(new_key.as_str())?.write_entry(entry.clone())?;
+    self.shard(old_key.as_str())?.delete_entries(&[ins])?;
+    return Ok(entry);
+  }
+  fn delete_entries(&self, ins_list: &[String]) -> Result<Vec<EntryObject>, String> {
+    let _lock = self.lock(true)?;
+    let found = self.find_shards(ins_list)?;
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for ins in ins_list {
+      match found.get(ins) {
+        Some(key) => groups.entry(key.clone()).or_default().push(ins.clone()),
+        None => return Err(format!("No entry found with ins {}", ins)),
+      }
+    }
+    let mut deleted: Vec<EntryObject> = vec![];
+    for (key, group) in groups {
+      deleted.extend(self.shard(key.as_str())?.delete_entries(&group)?);
+    }
+    deleted.sort();
+    return Ok(deleted);
+  }
+  fn compact(&self) -> Result<usize, String> {
+    let _lock = self.lock(true)?;
+    let mut count = 0;
+    for key in self.shard_keys()? {
+      count += self.shard(key.as_str())?.compact()?;
+    }
+    return Ok(count);
+  }
+  fn take_diagnostics(&self) -> Vec<LoadDiagnostic> {
+    return self.diagnostics.replace(vec![]);
+  }
+  fn take_recovery(&self) -> Vec<String> {
+    return self.recovery.replace(vec![]);
+  }
+  fn list_backups(&self) -> Result<Vec<BackupInfo>, String> {
+    let _lock = self.lock(false)?;
+    let mut backups: Vec<BackupInfo> = vec![];
+    for key in self.shard_keys()? {
+      backups.extend(self.shard(key.as_str())?.list_backups()?);
+    }
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.created));
+    return Ok(backups);
+  }
+  // the backups of every shard, and the shards of past periods themselves
+  fn archive(&self) -> Result<Vec<String>, String> {
+    let _lock = self.lock(true)?;
+    let current_key = self.period.key(Local::now().naive_local().date());
+    let mut archived: Vec<String> = vec![];
+    for key in self.shard_keys()? {
+      let shard = self.shard(key.as_str())?;
+      archived.extend(shard.archive()?);
+      if key < current_key {
+        archived.extend(shard.archive_journal()?);
+      }
+    }
+    return Ok(archived);
+  }
+  // every shard is its own chain
+  fn verify_chain(&self) -> Result<Vec<ChainReport>, String> {
+    let _lock = self.lock(false)?;
+    let mut reports: Vec<ChainReport> = vec![];
+    for key in self.shard_keys()? {
+      reports.extend(self.shard(key.as_str())?.verify_chain()?);
+    }
+    return Ok(reports);
+  }
+  fn reseal_chain(&self) -> Result<usize, String> {
+    let _lock = self.lock(true)?;
+    let mut count = 0;
+    for key in self.shard_keys()? {
+      count += self.shard(key.as_str())?.reseal_chain()?;
+    }
+    return Ok(count);
+  }
+}
+
+impl ShardedPersistence {
+  pub fn new(
+    config: FileSystemConfiguration,
+    period: ShardPeriod,
+    loader: fn(FileSystemConfiguration) -> FileSystemPersistence,
+  ) -> ShardedPersistence {
+    return ShardedPersistence {
+      config,
+      period,
+      loader,
+      diagnostics: RefCell::new(vec![]),
+      recovered: RefCell::new(BTreeSet::new()),
+      recovery: RefCell::new(vec![]),
+    };
+  }
+  // every shard keeps its own lock as well, this one covers operations across shards
+  fn lock(&self, exclusive: bool) -> Result<FileLock, String> {
+    return FileLock::acquire(
+      format!(
+        "{}/{}-shards.lock",
+        self.config.file_path, self.config.file_name
+      )
+      .as_str(),
+      exclusive,
+      self.config.lock_timeout_ms,
+    );
+  }
+  fn shard_name(&self, key: &str) -> String {
+    return format!("{}-{}", self.config.file_name, key);
+  }
+  fn shard_config(&self, key: &str) -> FileSystemConfiguration {
+    return FileSystemConfiguration {
+      file_name: self.shard_name(key),
+      ..self.config.clone()
+    };
+  }
+  // a shard is checked the first time it is opened, the others are never looked at
+  fn shard(&self, key: &str) -> Result<FileSystemPersistence, String> {
+    let shard = (self.loader)(self.shard_config(key));
+    if self.recovered.borrow_mut().insert(key.to_string()) {
+      self.recovery.borrow_mut().extend(shard.recover()?);
+    }
+    return Ok(shard);
+  }
+  fn shard_key_for(&self, entry: &EntryObject) -> Result<String, String> {
+    return NaiveDate::parse_from_str(entry.metadata.date.as_str(), "%d-%b-%y")
+      .map(|date| self.period.key(date))
+      .map_err(|_err| format!("Invalid date: {}", entry.metadata.date));
+  }
+  // oldest first
+  fn shard_keys(&self) -> Result<Vec<String>, String> {
+    let prefix = format!("{}-", self.config.file_name);
+    let extension = self.config.file_current_extension.as_str();
+    let dir = read_dir(Path::new(self.config.file_path.as_str()))
+      .map_err(|err| format!("Couldn't list {}: {}", self.config.file_path, err))?;
+    let mut keys: Vec<String> = vec![];
+    for item in dir.flatten() {
+      let name = item.file_name().to_string_lossy().to_string();
+      // archived shards end in .gz
+      let name = name
+        .strip_suffix(ARCHIVE_EXTENSION)
+        .unwrap_or(name.as_str());
+      if let Some(key) = name
+        .strip_prefix(prefix.as_str())
+        .and_then(|rest| rest.strip_suffix(extension))
+      {
+        if self.period.range(key).is_some() {
+          keys.push(key.to_string());
+        }
+      }
+    }
+    keys.sort();
+    keys.dedup();
+    return Ok(keys);
+  }
+  fn read_shards(&self, keys: &[String]) -> Result<Vec<EntryObject>, String> {
+    let mut entries: Vec<EntryObject> = vec![];
+    for key in keys {
+      let shard = self.shard(key.as_str())?;
+      let result = shard.read_entries();
+      self.collect_diagnostics(&shard, key.as_str());
+      entries.extend(result?);
+    }
+    return Ok(entries);
+  }
+  fn collect_diagnostics(&self, shard: &FileSystemPersistence, key: &str) {
+    for mut diagnostic in shard.take_diagnostics() {
+      diagnostic.reason = format!(
+        "{}{}: {}",
+        self.shard_name(key),
+        self.config.file_current_extension,
+        diagnostic.reason
+      );
+      self.diagnostics.borrow_mut().push(diagnostic);
+    }
+  }
+  // ins -> shard key for every ins that was found, newest period first since that's where
+  // recent entries are, stops once every ins is found
+  fn find_shards(&self, ins_list: &[String]) -> Result<BTreeMap<String, String>, String> {
+    let mut found: BTreeMap<String, String> = BTreeMap::new();
+    for key in self.shard_keys()?.into_iter().rev() {
+      let shard = self.shard(key.as_str())?;
+      for ins in ins_list {
+        if found.contains_key(ins) {
+          continue;
+        }
+        let result = shard.find_entry(ins.as_str());
+        self.collect_diagnostics(&shard, key.as_str());
+        if result?.is_some() {
+          found.insert(ins.clone(), key.clone());
+        }
+      }
+      if ins_list.iter().all(|ins| found.contains_key(ins)) {
+        break;
+      }
+    }
+    return Ok(found);
+  }
+  // every shard, the journal not yet moved into shards and the kept original with their backups,
+  // the caller holds the lock of the unsharded journal
+  pub fn encrypt_plaintext(&self) -> Result<usize, String> {
+    let key = match &self.config.encryption_key {
+      Some(key) => key,
+      None => return Ok(0),
+    };
+    let _lock = self.lock(true)?;
+    let mut count = (self.loader)(self.config.clone()).encrypt_plaintext()?;
+    for shard_key in self.shard_keys()? {
+      let config = self.shard_config(shard_key.as_str());
+      let _shard_lock = FileLock::acquire(
+        lock_path_string(&config).as_str(),
+        true,
+        config.lock_timeout_ms,
+      )?;
+      count += (self.loader)(config).encrypt_plaintext()?;
+    }
+    let kept_path_string = self.kept_path_string();
+    let kept_path = Path::new(kept_path_string.as_str());
+    if kept_path.exists() && FileSystemPersistence::encrypt_file(key, kept_path)? {
+      count += 1;
+    }
+    return Ok(count);
+  }
+  fn kept_path_string(&self) -> String {
+    return format!(
+      "{}/{}-unsharded{}",
+      self.config.file_path, self.config.file_name, self.config.file_backup_extension
+    );
+  }
+  // moves an unsharded journal into shards, the shards themselves are repaired when they are
+  // opened, see take_recovery
+  pub fn recover(&self) -> Result<Vec<String>, String> {
+    let journal_path_string = format!(
+      "{}/{}{}",
+      self.config.file_path, self.config.file_name, self.config.file_current_extension
+    );
+    if !Path::new(journal_path_string.as_str()).is_file() {
+      return Ok(vec![]);
+    }
+    let _lock = self.lock(true)?;
+    // another entrylogger may have moved it while this one waited for the lock
+    if !Path::new(journal_path_string.as_str()).is_file() {
+      return Ok(vec![]);
+    }
+    let mut messages: Vec<String> = vec![];
+    let journal = (self.loader)(FileSystemConfiguration {
+      load_mode: LoadMode::Strict,
+      ..self.config.clone()
+    });
+    messages.extend(journal.recover()?);
+    let mut groups: BTreeMap<String, Vec<EntryObject>> = BTreeMap::new();
+    let entries = journal.read_entries()?;
+    let count = entries.len();
+    for entry in entries {
+      groups
+        .entry(self.shard_key_for(&entry)?)
+        .or_default()
+        .push(entry);
+    }
+    // an interrupted migration is simply run again, entries already moved are skipped
+    for (key, group) in groups {
+      let shard = self.shard(key.as_str())?;
+      let existing = shard.read_entries()?;
+      let missing: Vec<EntryObject> = group
+        .into_iter()
+        .filter(|entry| !existing.contains(entry))
+        .collect();
+      shard.write_entries(missing)?;
+    }
+    let kept_path_string = self.kept_path_string();
+    rename(
+      Path::new(journal_path_string.as_str()),
+      Path::new(kept_path_string.as_str()),
+    )
+    .map_err(|err| format!("Couldn't move {}: {}", journal_path_string, err))?;
+    messages.extend(self.recovery.replace(vec![]));
+    messages.push(format!(
+      "Moved {} entries from {} into shards, the original was kept as {}",
+      count, journal_path_string, kept_path_string
+    ));
+    return Ok(messages);
+  }
+}
+
+/* -----------------------------------TESTS------------------------------------------ */
+#[cfg(test)]
+mod tests {
+  use super::super::test_support::{test_dir, test_entry_with};
+  use super::*;
+  #[test]
+  fn shard_range_test() {
+    assert_eq!(
+      ShardPeriod::Month.range("2024-02"),
+      Some((
+        NaiveDate::from_ymd_opt(2024, 2, 1).unwrap(),
+        NaiveDate::from_ymd_opt(2024, 2, 29).unwrap()
+      ))
+    );
+    assert_eq!(
+      ShardPeriod::Month.range("2024-12").unwrap().1,
+      NaiveDate::from_ymd_opt(2024, 12, 31).unwrap()
+    );
+    assert_eq!(ShardPeriod::Month.range("2024"), None);
+    assert_eq!(ShardPeriod::Year.range("2024-02"), None);
+    assert_eq!(ShardPeriod::Year.range("unsh"), None);
+  }
+  #[test]
+  fn sharded_persistence_test() {
+    let dir = test_dir("sharded");
+    let config = FileSystemConfiguration {
+      file_path: dir.to_str().unwrap().to_owned(),
+      ..FileSystemConfiguration::default()
+    };
+    let journal: FileSystemPersistence = FileSystemLoader::load(config.clone());
+    journal
+      .write_entries(vec![
+        test_entry_with("0000000000001", "13-oct-20", "fit", "run"),
+        test_entry_with("0000000000002", "01-nov-20", "fit", "run"),
+      ])
+      .unwrap();
+    let persistence = ShardedPersistence::new(
+      config,
+      ShardPeriod::Month,
+      <FileSystemPersistence as FileSystemLoader>::load,
+    );
+    assert_eq!(persistence.recover().unwrap().len(), 1);
+    assert!(!dir.join("entries.log").exists());
+    assert!(dir.join("entries-unsharded.bak").exists());
+    assert!(dir.join("entries-2020-10.log").exists());
+    assert_eq!(persistence.read_entries().unwrap().len(), 2);
+
+    persistence
+      .write_entry(test_entry_with("0000000000003", "02-nov-20", "fit", "run"))
+      .unwrap();
+    // a broken shard outside the range is never opened
+    std::fs::write(dir.join("entries-2020-12.log"), "broken\n").unwrap();
+    let filter = EntryFilter {
+      date_from: Some(NaiveDate::from_ymd_opt(2020, 11, 1).unwrap()),
+      date_to: Some(NaiveDate::from_ymd_opt(2020, 11, 30).unwrap()),
+      ..EntryFilter::new()
+    };
+    assert_eq!(
+      persistence.query_entries(&filter).unwrap(),
+      vec![
+        test_entry_with("0000000000002", "01-nov-20", "fit", "run"),
+        test_entry_with("0000000000003", "02-nov-20", "fit", "run")
+      ]
+    );
+    assert!(persistence.read_entries().is_err());
+    std::fs::remove_file(dir.join("entries-2020-12.log")).unwrap();
+
+    persistence
+      .update_entry(test_entry_with("0000000000001", "03-nov-20", "fit", "run"))
+      .unwrap();
+    assert_eq!(
+      std::fs::read_to_string(dir.join("entries-2020-10.log")).unwrap(),
+      ""
+    );
+    assert_eq!(
+      persistence
+        .delete_entries(&["0000000000002".to_string(), "0000000000003".to_string()])
+        .unwrap()
+        .len(),
+      2
+    );
+    assert_eq!(
+      persistence.read_entries().unwrap(),
+      vec![test_entry_with("0000000000001", "03-nov-20", "fit", "run")]
+    );
+
+    // past periods are archived whole and stay readable
+    let archived = persistence.archive().unwrap();
+    assert!(archived.contains(&format!("{}/entries-2020-11.log.gz", dir.to_str().unwrap())));
+    assert!(!dir.join("entries-2020-11.log").exists());
+    assert_eq!(
+      persistence.query_entries(&filter).unwrap(),
+      vec![test_entry_with("0000000000001", "03-nov-20", "fit", "run")]
+    );
+    persistence
+      .write_entry(test_entry_with("0000000000004", "04-nov-20", "fit", "run"))
+      .unwrap();
+    assert!(dir.join("entries-2020-11.log").exists());
+    assert_eq!(persistence.read_entries().unwrap().len(), 2);
+    // an ins taken in another shard isn't handed out again
+    assert_eq!(
+      persistence
+        .insert_entry(test_entry_with("0000000000001", "14-oct-20", "fit", "run"))
+        .unwrap()
+        .metadata
+        .ins,
+      Some("0000000000002".to_string())
+    );
+
+    // a shard is only checked once a command opens it
+    let torn = dir.join("entries-2019-01.log");
+    std::fs::write(&torn, "[ins:0000000000005 date:01-jan-19").unwrap();
+    let persistence = ShardedPersistence::new(
+      persistence.config.clone(),
+      ShardPeriod::Month,
+      <FileSystemPersistence as FileSystemLoader>::load,
+    );
+    assert_eq!(persistence.recover().unwrap(), Vec::<String>::new());
+    // the entry is found in the newest shard, the older ones aren't looked at
+    persistence
+      .delete_entries(&["0000000000004".to_string()])
+      .unwrap();
+    assert_eq!(persistence.take_recovery(), Vec::<String>::new());
+    assert_eq!(
+      std::fs::read_to_string(&torn).unwrap(),
+      "[ins:0000000000005 date:01-jan-19"
+    );
+    assert_eq!(persistence.read_entries().unwrap().len(), 2);
+    assert_eq!(persistence.take_recovery().len(), 1);
+    assert_eq!(std::fs::read_to_string(&torn).unwrap(), "");
+  }
+}
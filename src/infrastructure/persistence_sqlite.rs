@@ -0,0 +1,382 @@
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use rusqlite::{params, params_from_iter, types::Value, Connection};
+
+use application::*;
+use domain::*;
+
+use super::persistence_textfile::FileSystemConfiguration;
+
+// dates are stored as iso dates so they sort and compare in sql
+static SCHEMA: &str = "
+  CREATE TABLE IF NOT EXISTS entries (
+    ins TEXT UNIQUE,
+    date TEXT NOT NULL,
+    time TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    message TEXT NOT NULL
+  );
+  CREATE INDEX IF NOT EXISTS entries_date ON entries (date);
+  CREATE INDEX IF NOT EXISTS entries_time ON entries (time);
+  CREATE INDEX IF NOT EXISTS entries_tag ON entries (tag);
+";
+
+pub struct SqlitePersistence {
+  connection: Connection,
+}
+
+impl EntryPersistenceInterface for SqlitePersistence {
+  fn read_entries(&self) -> Result<Vec<EntryObject>, String> {
+    return self.query_entries(&EntryFilter::new());
+  }
+  fn query_entries(&self, filter: &EntryFilter) -> Result<Vec<EntryObject>, String> {
+    let mut conditions: Vec<&str> = vec![];
+    let mut values: Vec<Value> = vec![];
+    if let Some(from) = filter.date_from {
+      conditions.push("date >= ?");
+      values.push(Value::Text(from.format("%Y-%m-%d").to_string()));
+    }
+    if let Some(to) = filter.date_to {
+      conditions.push("date <= ?");
+      values.push(Value::Text(to.format("%Y-%m-%d").to_string()));
+    }
+    if let Some(tag) = &filter.tag {
+      conditions.push("tag = ?");
+      values.push(Value::Text(tag.clone()));
+    }
+    if let Some(time) = &filter.time {
+      conditions.push("time = ?");
+      values.push(Value::Text(time.clone()));
+    }
+    if let Some(message) = &filter.message {
+      conditions.push("instr(message, ?) > 0");
+      values.push(Value::Text(message.clone()));
+    }
+    let mut sql = "SELECT ins, date, time, tag, message FROM entries".to_string();
+    if !conditions.is_empty() {
+      sql.push_str(" WHERE ");
+      sql.push_str(conditions.join(" AND ").as_str());
+    }
+    sql.push_str(Self::order_by().as_str());
+    return self.select(sql.as_str(), values);
+  }
+  fn find_entry(&self, ins: &str) -> Result<Option<EntryObject>, String> {
+    let mut entries = self.select(
+      "SELECT ins, date, time, tag, message FROM entries WHERE ins = ?",
+      vec![Value::Text(ins.to_string())],
+    )?;
+    return Ok(entries.pop());
+  }
+  // rowid follows insertion order
+  fn for_each_entry(
+    &self,
+    reverse: bool,
+    visit: &mut dyn FnMut(EntryObject) -> bool,
+  ) -> Result<(), String> {
+    let sql = format!(
+      "SELECT ins, date, time, tag, message FROM entries ORDER BY rowid {}",
+      if reverse { "DESC" } else { "ASC" }
+    );
+    let mut statement = self
+      .connection
+      .prepare(sql.as_str())
+      .map_err(|err| err.to_string())?;
+    let mut rows = statement
+      .query([])
+      .map_err(|err| format!("Couldn't read entries: {}", err))?;
+    while let Some(row) = rows
+      .next()
+      .map_err(|err| format!("Couldn't read entries: {}", err))?
+    {
+      if !visit(Self::entry_from_row(row)?) {
+        break;
+      }
+    }
+    return Ok(());
+  }
+  fn write_entry(&self, entry: EntryObject) -> Result<EntryObject, String> {
+    self.insert_entries(std::slice::from_ref(&entry))?;
+    return Ok(entry);
+  }
+  // the unique ins column turns away an ins another process took in the meantime
+  fn insert_entry(&self, mut entry: EntryObject) -> Result<EntryObject, String> {
+    let mut last: Option<String> = None;
+    self.for_each_entry(true, &mut |stored| {
+      last = stored.metadata.ins;
+      return false;
+    })?;
+    let mut ins = ins_past(entry.metadata.ins.clone().unwrap_or_default(), last)?;
+    loop {
+      entry.metadata.ins = Some(ins.clone());
+      match self.insert_entries(std::slice::from_ref(&entry)) {
+        Ok(()) => return Ok(entry),
+        Err(_err) if self.find_entry(ins.as_str())?.is_some() => ins = next_ins(ins.as_str())?,
+        Err(err) => return Err(err),
+      }
+    }
+  }
+  fn write_entries(&self, entries: Vec<EntryObject>) -> Result<Vec<EntryObject>, String> {
+    self.insert_entries(&entries)?;
+    return Ok(entries);
+  }
+  fn update_entry(&self, entry: EntryObject) -> Result<EntryObject, String> {
+    let changed = self
+      .connection
+      .execute(
+        "UPDATE entries SET date = ?1, time = ?2, tag = ?3, message = ?4 WHERE ins = ?5",
+        params![
+          Self::to_iso_date(entry.metadata.date.as_str())?,
+          entry.metadata.time,
+          entry.metadata.tag,
+          entry.message,
+          entry.metadata.ins
+        ],
+      )
+      .map_err(|err| format!("Couldn't update entry: {}", err))?;
+    if changed == 0 {
+      return Err(format!(
+        "No entry found with ins {}",
+        entry.metadata.ins.unwrap_or_default()
+      ));
+    }
+    return Ok(entry);
+  }
+  fn delete_entries(&self, ins_list: &[String]) -> Result<Vec<EntryObject>, String> {
+    let transaction = self
+      .connection
+      .unchecked_transaction()
+      .map_err(|err| err.to_string())?;
+    let placeholders = vec!["?"; ins_list.len()].join(", ");
+    let values: Vec<Value> = ins_list
+      .iter()
+      .map(|ins| Value::Text(ins.clone()))
+      .collect();
+    let deleted = self.select(
+      format!(
+        "SELECT ins, date, time, tag, message FROM entries WHERE ins IN ({}){}",
+        placeholders,
+        Self::order_by()
+      )
+      .as_str(),
+      values.clone(),
+    )?;
+    for ins in ins_list {
+      if !deleted
+        .iter()
+        .any(|item| item.metadata.ins.as_ref() == Some(ins))
+      {
+        return Err(format!("No entry found with ins {}", ins));
+      }
+    }
+    transaction
+      .execute(
+        format!("DELETE FROM entries WHERE ins IN ({})", placeholders).as_str(),
+        params_from_iter(values),
+      )
+      .and_then(|_count| transaction.commit())
+      .map_err(|err| format!("Couldn't delete entries: {}", err))?;
+    return Ok(deleted);
+  }
+  fn compact(&self) -> Result<usize, String> {
+    self
+      .connection
+      .execute_batch("VACUUM")
+      .map_err(|err| format!("Couldn't compact database: {}", err))?;
+    let count: i64 = self
+      .connection
+      .query_row("SELECT count(*) FROM entries", [], |row| row.get(0))
+      .map_err(|err| err.to_string())?;
+    return Ok(count as usize);
+  }
+  // rows are typed columns, there is nothing a lenient read could skip
+  fn take_diagnostics(&self) -> Vec<LoadDiagnostic> {
+    return vec![];
+  }
+}
+
+impl SqlitePersistence {
+  // the database lives where the journal file would, file_current_extension defaults to .db
+  pub fn open(config: &FileSystemConfiguration) -> Result<SqlitePersistence, String> {
+    let path = format!(
+      "{}/{}{}",
+      config.file_path, config.file_name, config.file_current_extension
+    );
+    let connection =
+      Connection::open(path.as_str()).map_err(|err| format!("Couldn't open {}: {}", path, err))?;
+    connection
+      .busy_timeout(Duration::from_millis(config.lock_timeout_ms))
+      .and_then(|_ok| connection.execute_batch(SCHEMA))
+      .map_err(|err| format!("Couldn't prepare {}: {}", path, err))?;
+    return Ok(SqlitePersistence { connection });
+  }
+  // same order as EntryObject: date, time of day, ins with missing ones last, tag, message
+  fn order_by() -> String {
+    let cases: Vec<String> = TIME_VEC
+      .iter()
+      .enumerate()
+      .map(|(index, time)| format!("WHEN '{}' THEN {}", time, index))
+      .collect();
+    return format!(
+      " ORDER BY date, CASE time {} ELSE -1 END, ins IS NULL, CAST(ins AS INTEGER), tag, message",
+      cases.join(" ")
+    );
+  }
+  fn to_iso_date(date: &str) -> Result<String, String> {
+    return NaiveDate::parse_from_str(date, "%d-%b-%y")
+      .map(|value| value.format("%Y-%m-%d").to_string())
+      .map_err(|_err| format!("Invalid date: {}", date));
+  }
+  fn from_iso_date(date: &str) -> Result<String, String> {
+    return NaiveDate::parse_from_str(date, "%Y-%m-%d")
+      .map(|value| value.format("%d-%b-%y").to_string().to_lowercase())
+      .map_err(|_err| format!("Invalid date in database: {}", date));
+  }
+  // entries are sorted by ins, a row edited by hand mustn't break that
+  fn check_ins(ins: Option<String>) -> Result<Option<String>, String> {
+    match ins {
+      Some(ins) if ins.parse::<i64>().is_err() => {
+        return Err(format!("Invalid ins in database: {}", ins))
+      }
+      ins => return Ok(ins),
+    }
+  }
+  fn select(&self, sql: &str, values: Vec<Value>) -> Result<Vec<EntryObject>, String> {
+    let mut statement = self
+      .connection
+      .prepare(sql)
+      .map_err(|err| err.to_string())?;
+    let mut rows = statement
+      .query(params_from_iter(values))
+      .map_err(|err| format!("Couldn't read entries: {}", err))?;
+    let mut entries: Vec<EntryObject> = vec![];
+    while let Some(row) = rows
+      .next()
+      .map_err(|err| format!("Couldn't read entries: {}", err))?
+    {
+      entries.push(Self::entry_from_row(row)?);
+    }
+    return Ok(entries);
+  }
+  fn entry_from_row(row: &rusqlite::Row) -> Result<EntryObject, String> {
+    let read_error = |err: rusqlite::Error| format!("Couldn't read entries: {}", err);
+    let date: String = row.get(1).map_err(read_error)?;
+    let metadata = EntryMetadata {
+      ins: Self::check_ins(row.get(0).map_err(read_error)?)?,
+      date: Self::from_iso_date(date.as_str())?,
+      time: row.get(2).map_err(read_error)?,
+      tag: row.get(3).map_err(read_error)?,
+    };
+    return Ok(EntryObject {
+      metadata,
+      message: row.get(4).map_err(read_error)?,
+    });
+  }
+  fn insert_entries(&self, entries: &[EntryObject]) -> Result<(), String> {
+    let transaction = self
+      .connection
+      .unchecked_transaction()
+      .map_err(|err| err.to_string())?;
+    for entry in entries {
+      transaction
+        .execute(
+          "INSERT INTO entries (ins, date, time, tag, message) VALUES (?1, ?2, ?3, ?4, ?5)",
+          params![
+            entry.metadata.ins,
+            Self::to_iso_date(entry.metadata.date.as_str())?,
+            entry.metadata.time,
+            entry.metadata.tag,
+            entry.message
+          ],
+        )
+        .map_err(|err| match err.sqlite_error_code() {
+          Some(rusqlite::ErrorCode::ConstraintViolation) => format!(
+            "Entry with ins {} already exists",
+            entry.metadata.ins.clone().unwrap_or_default()
+          ),
+          _ => format!("Couldn't write entry: {}", err),
+        })?;
+    }
+    transaction
+      .commit()
+      .map_err(|err| format!("Couldn't write entries: {}", err))?;
+    return Ok(());
+  }
+}
+
+/* -----------------------------------TESTS------------------------------------------ */
+#[cfg(test)]
+mod tests {
+  use super::super::test_support::{test_dir, test_entry_with};
+  use super::*;
+  #[test]
+  fn sqlite_persistence_test() {
+    let dir = test_dir("sqlite");
+    let config = FileSystemConfiguration {
+      file_path: dir.to_str().unwrap().to_owned(),
+      file_current_extension: ".db".to_owned(),
+      ..FileSystemConfiguration::default()
+    };
+    let persistence = SqlitePersistence::open(&config).unwrap();
+    let mut entries = vec![
+      test_entry_with("0000000000003", "01-jan-21", "fit", "run"),
+      test_entry_with("0000000000002", "13-oct-20", "work", "report"),
+      test_entry_with("0000000000001", "13-oct-20", "fit", "swim [pool]"),
+    ];
+    entries[2].metadata.time = "night".to_string();
+    persistence.write_entries(entries.clone()).unwrap();
+    assert_eq!(
+      persistence.write_entry(entries[0].clone()),
+      Err("Entry with ins 0000000000003 already exists".to_string())
+    );
+    let mut sorted = entries.clone();
+    sorted.sort();
+    assert_eq!(persistence.read_entries().unwrap(), sorted);
+
+    let filter = EntryFilter {
+      date_to: Some(NaiveDate::from_ymd_opt(2020, 12, 31).unwrap()),
+      tag: Some("fit".to_string()),
+      message: Some("[pool]".to_string()),
+      ..EntryFilter::new()
+    };
+    assert_eq!(
+      persistence.query_entries(&filter).unwrap(),
+      vec![entries[2].clone()]
+    );
+
+    let mut edited = entries[1].clone();
+    edited.message = "edited".to_string();
+    persistence.update_entry(edited.clone()).unwrap();
+    assert!(persistence
+      .delete_entries(&["0000000000002".to_string(), "0000000000009".to_string()])
+      .is_err());
+    assert_eq!(
+      persistence
+        .delete_entries(&["0000000000002".to_string()])
+        .unwrap(),
+      vec![edited]
+    );
+    // a taken ins is turned away by the table, the post moves on to the next one
+    assert_eq!(
+      persistence
+        .insert_entry(test_entry_with("0000000000003", "02-jan-21", "fit", "walk"))
+        .unwrap()
+        .metadata
+        .ins,
+      Some("0000000000004".to_string())
+    );
+    assert_eq!(persistence.compact().unwrap(), 3);
+
+    persistence
+      .connection
+      .execute(
+        "INSERT INTO entries (ins, date, time, tag, message) VALUES ('abc', '2021-01-03', 'night', 'fit', 'run')",
+        [],
+      )
+      .unwrap();
+    assert_eq!(
+      persistence.read_entries(),
+      Err("Invalid ins in database: abc".to_string())
+    );
+  }
+}
@@ -1,100 +1,1412 @@
+use std::cell::RefCell;
 use std::io::prelude::*;
+use std::io::{BufReader, SeekFrom};
 use std::{
-  fs::{rename, OpenOptions},
-  path::Path,
+  fs::{copy, read_dir, remove_file, rename, File, OpenOptions},
+  path::{Path, PathBuf},
 };
 
+use chrono::{Local, NaiveDateTime};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
 use regex::Regex;
+use sha2::{Digest, Sha256};
 
 use application::*;
 use domain::*;
 
+use super::encryption::{EncryptedConverter, EncryptionKey, ENCRYPTED_PREFIX};
+use super::entry_index::{EntryIndex, IndexRecord, IndexStamp};
+use super::file_lock::FileLock;
+use super::persistence_sharded::ShardPeriod;
+
 pub struct FileSystemPersistence {
   config: FileSystemConfiguration,
+  converter: Box<dyn EntryStringConverter>,
+  diagnostics: RefCell<Vec<LoadDiagnostic>>,
 }
 
 impl EntryPersistenceInterface for FileSystemPersistence {
   fn read_entries(&self) -> Result<Vec<EntryObject>, String> {
+    let _lock = self.lock(false)?;
+    return self.load_entries(self.config.load_mode);
+  }
+  // date and tag filters only read the lines the index points at
+  fn query_entries(&self, filter: &EntryFilter) -> Result<Vec<EntryObject>, String> {
+    let _lock = self.lock(false)?;
+    let mut entries: Vec<EntryObject> = vec![];
+    let mut keep = |entry: EntryObject| {
+      if filter.matches(&entry) {
+        entries.push(entry);
+      }
+      return true;
+    };
+    let indexed = filter.date_from.is_some() || filter.date_to.is_some() || filter.tag.is_some();
+    if indexed && self.keeps_index() {
+      let index = self.index()?;
+      let records: Vec<&IndexRecord> = index
+        .records
+        .iter()
+        .filter(|record| record.matches(filter))
+        .collect();
+      self.visit_records(self.config.load_mode, &records, &mut keep)?;
+    } else {
+      self.visit_entries(self.config.load_mode, false, &mut keep)?;
+    }
+    entries.sort();
+    return Ok(entries);
+  }
+  fn find_entry(&self, ins: &str) -> Result<Option<EntryObject>, String> {
+    let _lock = self.lock(false)?;
+    return self.lookup_entry(ins);
+  }
+  fn write_entry(&self, entry: EntryObject) -> Result<EntryObject, String> {
+    let _lock = self.lock(true)?;
+    self.unarchive()?;
+    self.append_entries(std::slice::from_ref(&entry))?;
+    return Ok(entry);
+  }
+  // another entrylogger can't post between picking the ins and appending the entry
+  fn insert_entry(&self, mut entry: EntryObject) -> Result<EntryObject, String> {
+    let _lock = self.lock(true)?;
+    self.unarchive()?;
+    let mut last: Option<String> = None;
+    self.visit_entries(self.config.load_mode, true, &mut |stored| {
+      last = stored.metadata.ins;
+      return false;
+    })?;
+    let mut ins = ins_past(entry.metadata.ins.unwrap_or_default(), last)?;
+    while self.lookup_entry(ins.as_str())?.is_some() {
+      ins = next_ins(ins.as_str())?;
+    }
+    entry.metadata.ins = Some(ins);
+    self.append_entries(std::slice::from_ref(&entry))?;
+    return Ok(entry);
+  }
+  fn write_entries(&self, entries: Vec<EntryObject>) -> Result<Vec<EntryObject>, String> {
+    let _lock = self.lock(true)?;
+    self.unarchive()?;
+    self.append_entries(&entries)?;
+    return Ok(entries);
+  }
+  fn update_entry(&self, entry: EntryObject) -> Result<EntryObject, String> {
+    let _lock = self.lock(true)?;
+    self.unarchive()?;
+    let mut entries = self.load_entries(LoadMode::Strict)?;
+    let position = entries
+      .iter()
+      .position(|item| item.metadata.ins == entry.metadata.ins);
+    match position {
+      Some(index) => entries[index] = entry.clone(),
+      None => {
+        return Err(format!(
+          "No entry found with ins {}",
+          entry.metadata.ins.unwrap_or_default()
+        ))
+      }
+    }
+    self.rewrite_entries(entries)?;
+    return Ok(entry);
+  }
+  fn delete_entries(&self, ins_list: &[String]) -> Result<Vec<EntryObject>, String> {
+    let _lock = self.lock(true)?;
+    self.unarchive()?;
+    let entries = self.load_entries(LoadMode::Strict)?;
+    for ins in ins_list {
+      if !entries
+        .iter()
+        .any(|item| item.metadata.ins.as_ref() == Some(ins))
+      {
+        return Err(format!("No entry found with ins {}", ins));
+      }
+    }
+    let (deleted, kept): (Vec<EntryObject>, Vec<EntryObject>) =
+      entries
+        .into_iter()
+        .partition(|item| match &item.metadata.ins {
+          Some(ins) => ins_list.contains(ins),
+          None => false,
+        });
+    self.rewrite_entries(kept)?;
+    return Ok(deleted);
+  }
+  // an archived journal is compacted and archived again
+  fn compact(&self) -> Result<usize, String> {
+    let _lock = self.lock(true)?;
+    let archived = self.is_archived();
+    self.unarchive()?;
+    let entries = self.load_entries(LoadMode::Strict)?;
+    let count = entries.len();
+    self.rewrite_entries(entries)?;
+    if archived {
+      self.archive_current()?;
+    }
+    return Ok(count);
+  }
+  fn for_each_entry(
+    &self,
+    reverse: bool,
+    visit: &mut dyn FnMut(EntryObject) -> bool,
+  ) -> Result<(), String> {
+    let _lock = self.lock(false)?;
+    return self.visit_entries(self.config.load_mode, reverse, visit);
+  }
+  fn take_diagnostics(&self) -> Vec<LoadDiagnostic> {
+    return self.diagnostics.replace(vec![]);
+  }
+  fn list_backups(&self) -> Result<Vec<BackupInfo>, String> {
+    let _lock = self.lock(false)?;
+    let mut infos: Vec<BackupInfo> = vec![];
+    for (path, created) in self.backups()? {
+      let read_error = |err: std::io::Error| format!("Couldn't read {}: {}", path.display(), err);
+      let mut content = String::new();
+      Self::open_lines(&path)
+        .and_then(|mut reader| reader.read_to_string(&mut content))
+        .map_err(read_error)?;
+      infos.push(BackupInfo {
+        path: path.display().to_string(),
+        created,
+        entries: content.lines().filter(|line| !line.is_empty()).count(),
+        size: path.metadata().map_err(read_error)?.len(),
+      });
+    }
+    return Ok(infos);
+  }
+  // compresses every backup, past shards are archived by ShardedPersistence
+  fn archive(&self) -> Result<Vec<String>, String> {
+    let _lock = self.lock(true)?;
+    let mut archived: Vec<String> = vec![];
+    for (path, _created) in self.backups()? {
+      if Self::is_compressed(&path) {
+        continue;
+      }
+      let target = PathBuf::from(format!("{}{}", path.display(), ARCHIVE_EXTENSION));
+      self.compress_file(&path, &target)?;
+      remove_file(&path).map_err(|err| format!("Couldn't remove {}: {}", path.display(), err))?;
+      archived.push(target.display().to_string());
+    }
+    self.sync_directory();
+    return Ok(archived);
+  }
+  fn verify_chain(&self) -> Result<Vec<ChainReport>, String> {
+    let _lock = self.lock(false)?;
+    return Ok(vec![self.chain_report()?]);
+  }
+  // keeps every line as it is and in its order, only the seals are written anew
+  fn reseal_chain(&self) -> Result<usize, String> {
+    if !self.config.hash_chain {
+      return Err("hash_chain isn't enabled, there is no chain to seal".to_string());
+    }
+    let _lock = self.lock(true)?;
+    self.unarchive()?;
+    let path = self.current_path_string();
+    let mut reader = BufReader::new(self.open_current()?);
+    let mut content = String::new();
+    let mut previous = String::new();
+    let mut count = 0;
+    loop {
+      let mut raw: Vec<u8> = vec![];
+      if reader
+        .read_until(b'\n', &mut raw)
+        .map_err(|err| format!("Couldn't read {}: {}", path, err))?
+        == 0
+      {
+        break;
+      }
+      if raw.last() == Some(&b'\n') {
+        raw.pop();
+      }
+      if raw.is_empty() {
+        continue;
+      }
+      let text = String::from_utf8(raw)
+        .map_err(|_err| format!("Line {} of {}: Invalid UTF-8", count + 1, path))?;
+      let unsealed = split_seal(text.as_str()).map_or(text.as_str(), |(_seal, content)| content);
+      let sealed = seal_line(previous.as_str(), unsealed);
+      previous = sealed[SEAL_PREFIX.len()..SEAL_PREFIX.len() + SEAL_HASH_LENGTH].to_string();
+      content.push_str((sealed + "\n").as_str());
+      count += 1;
+    }
+    self.replace_current(content.as_str())?;
+    // every offset moved
+    self.rebuild_index(self.open_current()?)?;
+    return Ok(count);
+  }
+}
+
+impl FileSystemPersistence {
+  // shared for reads, exclusive for anything that writes
+  fn lock(&self, exclusive: bool) -> Result<FileLock, String> {
+    return FileLock::acquire(
+      self.lock_path_string().as_str(),
+      exclusive,
+      self.config.lock_timeout_ms,
+    );
+  }
+  // rewrites always load strictly so a lenient read never drops lines from the file
+  fn load_entries(&self, mode: LoadMode) -> Result<Vec<EntryObject>, String> {
     let mut res: Vec<EntryObject> = vec![];
-    // let display = path.display();
-    // let mut file = File::open(&path).unwrap();
-    let path = format!(
+    self.visit_entries(mode, false, &mut |entry| {
+      res.push(entry);
+      return true;
+    })?;
+    return Ok(res);
+  }
+  // indexed when the journal keeps an index, the caller holds the lock
+  fn lookup_entry(&self, ins: &str) -> Result<Option<EntryObject>, String> {
+    let mut found: Option<EntryObject> = None;
+    let mut find = |entry: EntryObject| {
+      if entry.metadata.ins.as_deref() == Some(ins) {
+        found = Some(entry);
+        return false;
+      }
+      return true;
+    };
+    if self.keeps_index() {
+      let index = self.index()?;
+      let records: Vec<&IndexRecord> = index
+        .records
+        .iter()
+        .filter(|record| record.may_have_ins(ins))
+        .collect();
+      self.visit_records(self.config.load_mode, &records, &mut find)?;
+    } else {
+      self.visit_entries(self.config.load_mode, false, &mut find)?;
+    }
+    return Ok(found);
+  }
+  // parses one line at a time in file order or from the end, stops as soon as visit returns false
+  fn visit_entries(
+    &self,
+    mode: LoadMode,
+    reverse: bool,
+    visit: &mut dyn FnMut(EntryObject) -> bool,
+  ) -> Result<(), String> {
+    let path = self.current_path_string();
+    let read_error = |err: std::io::Error| format!("Couldn't read {}: {}", path, err);
+    if reverse && !self.is_archived() {
+      let mut lines = ReverseLines::new(self.open_current()?).map_err(read_error)?;
+      while let Some((offset, raw)) = lines.next_line().map_err(read_error)? {
+        if !self.visit_line(mode, raw, || self.line_number(offset), visit)? {
+          break;
+        }
+      }
+      return Ok(());
+    }
+    let mut reader = self.current_lines()?;
+    let mut line = 0;
+    // a compressed journal can't be read from the end, reading it reversed holds all its lines
+    // in memory before the first one is visited
+    let mut collected: Vec<(usize, Vec<u8>)> = vec![];
+    loop {
+      let mut raw: Vec<u8> = vec![];
+      if reader.read_until(b'\n', &mut raw).map_err(read_error)? == 0 {
+        break;
+      }
+      if raw.last() == Some(&b'\n') {
+        raw.pop();
+      }
+      line += 1;
+      if reverse {
+        collected.push((line, raw));
+      } else if !self.visit_line(mode, raw, || Ok(line), visit)? {
+        return Ok(());
+      }
+    }
+    for (line, raw) in collected.into_iter().rev() {
+      if !self.visit_line(mode, raw, || Ok(line), visit)? {
+        break;
+      }
+    }
+    return Ok(());
+  }
+  // reads just the lines the index points at, in the order given
+  fn visit_records(
+    &self,
+    mode: LoadMode,
+    records: &[&IndexRecord],
+    visit: &mut dyn FnMut(EntryObject) -> bool,
+  ) -> Result<(), String> {
+    let path = self.current_path_string();
+    let mut file = File::open(Path::new(path.as_str()))
+      .map_err(|err| format!("Couldn't open {}: {}", path, err))?;
+    for record in records {
+      let mut raw = vec![0; record.length as usize];
+      file
+        .seek(SeekFrom::Start(record.offset))
+        .and_then(|_position| file.read_exact(&mut raw))
+        .map_err(|err| format!("Couldn't read {}: {}", path, err))?;
+      if !self.visit_line(mode, raw, || Ok(record.line), visit)? {
+        break;
+      }
+    }
+    return Ok(());
+  }
+  // sealed and unsealed lines are read alike, whether hash_chain is on or not
+  fn parse_line(&self, text: String) -> Result<EntryObject, String> {
+    match split_seal(text.as_str()) {
+      Some((_seal, content)) => return self.converter.parse_string(content.to_string()),
+      None => return self.converter.parse_string(text),
+    }
+  }
+  // the hash the next appended line links to, empty for an empty or unsealed journal
+  fn last_seal(&self) -> Result<String, String> {
+    let path = self.current_path_string();
+    let read_error = |err: std::io::Error| format!("Couldn't read {}: {}", path, err);
+    let mut lines = ReverseLines::new(self.open_current()?).map_err(read_error)?;
+    while let Some((_offset, raw)) = lines.next_line().map_err(read_error)? {
+      if raw.is_empty() {
+        continue;
+      }
+      let text = String::from_utf8_lossy(&raw);
+      return Ok(
+        split_seal(&text)
+          .map(|(seal, _content)| seal.to_string())
+          .unwrap_or_default(),
+      );
+    }
+    return Ok(String::new());
+  }
+  // the line number is only worked out when a line can't be parsed
+  fn visit_line(
+    &self,
+    mode: LoadMode,
+    raw: Vec<u8>,
+    line: impl FnOnce() -> Result<usize, String>,
+    visit: &mut dyn FnMut(EntryObject) -> bool,
+  ) -> Result<bool, String> {
+    if raw.is_empty() {
+      return Ok(true);
+    }
+    let parsed = match String::from_utf8(raw) {
+      Ok(text) => self
+        .parse_line(text.clone())
+        .map_err(|reason| (text, reason)),
+      Err(err) => Err((
+        String::from_utf8_lossy(err.as_bytes()).to_string(),
+        "Invalid UTF-8".to_string(),
+      )),
+    };
+    match parsed {
+      Ok(entry) => return Ok(visit(entry)),
+      Err((raw, reason)) => match mode {
+        LoadMode::Strict => {
+          return Err(format!(
+            "Line {} of {}: {}",
+            line()?,
+            self.current_path_string(),
+            reason
+          ))
+        }
+        LoadMode::Lenient => {
+          self.diagnostics.borrow_mut().push(LoadDiagnostic {
+            line: line()?,
+            raw,
+            reason,
+          });
+          return Ok(true);
+        }
+      },
+    }
+  }
+  // counts the newlines before a byte offset, in chunks
+  fn line_number(&self, offset: u64) -> Result<usize, String> {
+    let path = self.current_path_string();
+    let file = File::open(Path::new(path.as_str()))
+      .map_err(|err| format!("Couldn't open {}: {}", path, err))?;
+    let mut reader = BufReader::new(file.take(offset));
+    let mut count = 1;
+    loop {
+      let chunk = reader
+        .fill_buf()
+        .map_err(|err| format!("Couldn't read {}: {}", path, err))?;
+      if chunk.is_empty() {
+        return Ok(count);
+      }
+      count += chunk.iter().filter(|&&byte| byte == b'\n').count();
+      let length = chunk.len();
+      reader.consume(length);
+    }
+  }
+  fn current_path_string(&self) -> String {
+    return format!(
       "{}/{}{}",
       self.config.file_path.to_owned(),
       self.config.file_name.to_owned(),
       self.config.file_current_extension.to_owned()
     );
-    let mut file = OpenOptions::new()
+  }
+  // created empty when missing, like the journal of a fresh install
+  fn open_current(&self) -> Result<File, String> {
+    let path = self.current_path_string();
+    return OpenOptions::new()
       .write(true)
       .read(true)
       .create(true)
+      .truncate(false)
       .open(Path::new(path.as_str()))
-      .unwrap();
-    let mut content = String::new();
-    file.read_to_string(&mut content).unwrap();
-    let string_vec: Vec<&str> = content.split("\n").collect();
-    for split_string in string_vec {
-      if split_string.len() > 0 {
-        let obj = self.parse_string(split_string.to_string()).unwrap();
-        res.push(obj);
+      .map_err(|err| format!("Couldn't open {}: {}", path, err));
+  }
+  fn archived_path_string(&self) -> String {
+    return format!("{}{}", self.current_path_string(), ARCHIVE_EXTENSION);
+  }
+  // an uncompressed journal next to the archive is the newer one, recover removes the archive
+  fn is_archived(&self) -> bool {
+    return !Path::new(self.current_path_string().as_str()).exists()
+      && Path::new(self.archived_path_string().as_str()).exists();
+  }
+  fn is_compressed(path: &Path) -> bool {
+    return path.to_string_lossy().ends_with(ARCHIVE_EXTENSION);
+  }
+  // decompresses on the fly when the file is gzipped
+  fn open_lines(path: &Path) -> std::io::Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    if Self::is_compressed(path) {
+      return Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))));
+    }
+    return Ok(Box::new(BufReader::new(file)));
+  }
+  fn current_lines(&self) -> Result<Box<dyn BufRead>, String> {
+    if self.is_archived() {
+      let path = self.archived_path_string();
+      return Self::open_lines(Path::new(path.as_str()))
+        .map_err(|err| format!("Couldn't open {}: {}", path, err));
+    }
+    return Ok(Box::new(BufReader::new(self.open_current()?)));
+  }
+  // written next to the target and renamed, an interrupted run leaves the source untouched
+  fn compress_file(&self, source: &Path, target: &Path) -> Result<(), String> {
+    let temp = PathBuf::from(format!("{}.tmp", target.display()));
+    let write_error = |err: std::io::Error| format!("Couldn't write {}: {}", temp.display(), err);
+    let mut reader =
+      File::open(source).map_err(|err| format!("Couldn't open {}: {}", source.display(), err))?;
+    let mut encoder = GzEncoder::new(
+      File::create(&temp).map_err(write_error)?,
+      Compression::default(),
+    );
+    std::io::copy(&mut reader, &mut encoder)
+      .and_then(|_size| encoder.finish())
+      .and_then(|file| file.sync_all())
+      .map_err(write_error)?;
+    rename(&temp, target)
+      .map_err(|err| format!("Couldn't replace {}: {}", target.display(), err))?;
+    return Ok(());
+  }
+  // e.g. entries-2023-01.log.gz, reads keep working and the next write decompresses it
+  pub fn archive_journal(&self) -> Result<Option<String>, String> {
+    let _lock = self.lock(true)?;
+    if !Path::new(self.current_path_string().as_str()).exists() {
+      return Ok(None);
+    }
+    self.archive_current()?;
+    return Ok(Some(self.archived_path_string()));
+  }
+  fn archive_current(&self) -> Result<(), String> {
+    let current_path_string = self.current_path_string();
+    let archived_path_string = self.archived_path_string();
+    self.compress_file(
+      Path::new(current_path_string.as_str()),
+      Path::new(archived_path_string.as_str()),
+    )?;
+    remove_file(Path::new(current_path_string.as_str()))
+      .map_err(|err| format!("Couldn't remove {}: {}", current_path_string, err))?;
+    let _ = remove_file(Path::new(self.index_path_string().as_str()));
+    self.sync_directory();
+    return Ok(());
+  }
+  // writes go to the uncompressed journal
+  fn unarchive(&self) -> Result<(), String> {
+    if !self.is_archived() {
+      return Ok(());
+    }
+    let archived_path_string = self.archived_path_string();
+    let temp_path_string = self.temp_path_string();
+    let mut content: Vec<u8> = vec![];
+    Self::open_lines(Path::new(archived_path_string.as_str()))
+      .and_then(|mut reader| reader.read_to_end(&mut content))
+      .map_err(|err| format!("Couldn't read {}: {}", archived_path_string, err))?;
+    let mut file = File::create(Path::new(temp_path_string.as_str()))
+      .map_err(|err| format!("Couldn't create {}: {}", temp_path_string, err))?;
+    file
+      .write_all(&content)
+      .and_then(|_ok| file.sync_all())
+      .map_err(|err| format!("Couldn't write {}: {}", temp_path_string, err))?;
+    rename(
+      Path::new(temp_path_string.as_str()),
+      Path::new(self.current_path_string().as_str()),
+    )
+    .map_err(|err| format!("Couldn't replace {}: {}", self.current_path_string(), err))?;
+    remove_file(Path::new(archived_path_string.as_str()))
+      .map_err(|err| format!("Couldn't remove {}: {}", archived_path_string, err))?;
+    self.sync_directory();
+    return Ok(());
+  }
+  // an index would show ins, date and tag of an encrypted journal in plain text,
+  // and byte offsets mean nothing in a compressed one
+  fn keeps_index(&self) -> bool {
+    return self.config.encryption_key.is_none() && !self.is_archived();
+  }
+  fn index_path_string(&self) -> String {
+    return format!("{}.idx", self.current_path_string());
+  }
+  // the stored index while it still matches the journal's size and mtime, a rebuilt one otherwise
+  fn index(&self) -> Result<EntryIndex, String> {
+    let file = self.open_current()?;
+    let current = IndexStamp::of(self.current_path_string().as_str(), 0)?;
+    if let Some(index) = EntryIndex::load(self.index_path_string().as_str(), &current) {
+      return Ok(index);
+    }
+    return self.rebuild_index(file);
+  }
+  // lines that can't be parsed are indexed too, reads still report them
+  fn rebuild_index(&self, file: File) -> Result<EntryIndex, String> {
+    let path = self.current_path_string();
+    let mut reader = BufReader::new(file);
+    let mut records: Vec<IndexRecord> = vec![];
+    let mut offset: u64 = 0;
+    let mut line = 0;
+    loop {
+      let mut raw: Vec<u8> = vec![];
+      let length = reader
+        .read_until(b'\n', &mut raw)
+        .map_err(|err| format!("Couldn't read {}: {}", path, err))?;
+      if length == 0 {
+        break;
       }
+      if raw.last() == Some(&b'\n') {
+        raw.pop();
+      }
+      line += 1;
+      if !raw.is_empty() {
+        let entry = String::from_utf8(raw.clone())
+          .ok()
+          .and_then(|text| self.parse_line(text).ok());
+        records.push(IndexRecord::new(
+          line,
+          offset,
+          raw.len() as u64,
+          entry.as_ref(),
+        ));
+      }
+      offset += length as u64;
     }
-    return Ok(res);
+    let index = EntryIndex {
+      records,
+      stamp: IndexStamp::of(path.as_str(), line)?,
+    };
+    // only a cache, without write access it's rebuilt on every read
+    if self.keeps_index() {
+      let _ = index.write(self.index_path_string().as_str());
+    }
+    return Ok(index);
   }
-  fn write_entry(&self, entry: EntryObject) -> Result<EntryObject, String> {
-    let mut entries = self.read_entries()?;
-    let current_path_string = format!(
+  // journals from before rotated backups kept a single one without timestamp
+  fn legacy_backup_path_string(&self) -> String {
+    return format!(
       "{}/{}{}",
       self.config.file_path.to_owned(),
       self.config.file_name.to_owned(),
-      self.config.file_current_extension.to_owned()
+      self.config.file_backup_extension.to_owned()
     );
-    let backup_path_string = format!(
-      "{}/{}{}",
+  }
+  // named after the journal with its extension, the text and jsonl journals can share a directory
+  fn backup_path_string(&self, created: NaiveDateTime) -> String {
+    return format!(
+      "{}/{}{}.{}{}",
       self.config.file_path.to_owned(),
       self.config.file_name.to_owned(),
+      self.config.file_current_extension.to_owned(),
+      created.format(BACKUP_TIMESTAMP_FORMAT),
       self.config.file_backup_extension.to_owned()
     );
-    let current_path = Path::new(current_path_string.as_str());
+  }
+  // backups named without the extension are shared by the backends, only the ones in this format count
+  fn claims_backup(&self, path: &Path) -> bool {
+    let lines = match Self::open_lines(path) {
+      Ok(lines) => lines,
+      Err(_err) => return false,
+    };
+    for line in lines.lines() {
+      match line {
+        Ok(line) if line.is_empty() => continue,
+        Ok(line) => return self.parse_line(line).is_ok(),
+        Err(_err) => return false,
+      }
+    }
+    return true;
+  }
+  // newest first
+  fn backups(&self) -> Result<Vec<(PathBuf, NaiveDateTime)>, String> {
+    let mut backups: Vec<(PathBuf, NaiveDateTime)> = vec![];
+    let prefix = format!(
+      "{}{}.",
+      self.config.file_name, self.config.file_current_extension
+    );
+    let unnamed_prefix = format!("{}.", self.config.file_name);
+    let extension = self.config.file_backup_extension.as_str();
+    let legacy_path_string = self.legacy_backup_path_string();
+    let current_path_string = self.current_path_string();
+    let dir = read_dir(Path::new(self.config.file_path.as_str()))
+      .map_err(|err| format!("Couldn't list {}: {}", self.config.file_path, err))?;
+    for item in dir {
+      let path = match item {
+        Ok(item) => item.path(),
+        Err(_err) => continue,
+      };
+      let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name.to_string(),
+        None => continue,
+      };
+      // archived backups are named like the uncompressed ones plus .gz
+      let name = match name.strip_suffix(ARCHIVE_EXTENSION) {
+        Some(stem) if path.with_file_name(stem).exists() => continue,
+        Some(stem) => stem.to_string(),
+        None => name,
+      };
+      let plain_path = path.with_file_name(name.as_str());
+      if plain_path == Path::new(legacy_path_string.as_str())
+        && plain_path != Path::new(current_path_string.as_str())
+        && self.claims_backup(&path)
+      {
+        let modified = path
+          .metadata()
+          .and_then(|metadata| metadata.modified())
+          .map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+        let created = chrono::DateTime::<Local>::from(modified).naive_local();
+        backups.push((path, created));
+        continue;
+      }
+      let (timestamp, named) = match name
+        .strip_prefix(prefix.as_str())
+        .and_then(|rest| rest.strip_suffix(extension))
+      {
+        Some(timestamp) => (timestamp, true),
+        None => match name
+          .strip_prefix(unnamed_prefix.as_str())
+          .and_then(|rest| rest.strip_suffix(extension))
+        {
+          Some(timestamp) => (timestamp, false),
+          None => continue,
+        },
+      };
+      if let Ok(created) = NaiveDateTime::parse_from_str(timestamp, BACKUP_TIMESTAMP_FORMAT) {
+        if named || self.claims_backup(&path) {
+          backups.push((path, created));
+        }
+      }
+    }
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.1));
+    return Ok(backups);
+  }
+  // copies the current file to a new backup and drops the ones past backup_count or backup_max_age_days
+  fn rotate_backups(&self) -> Result<(), String> {
+    let mut created = Local::now().naive_local();
+    while Path::new(self.backup_path_string(created).as_str()).exists() {
+      created += chrono::Duration::milliseconds(1);
+    }
+    let current_path_string = self.current_path_string();
+    let backup_path_string = self.backup_path_string(created);
     let backup_path = Path::new(backup_path_string.as_str());
-    entries.push(entry.clone());
-    entries.sort();
-    rename(current_path, backup_path).unwrap();
-    let file = OpenOptions::new()
+    match &self.config.encryption_key {
+      Some(key) => self.encrypted_copy(key, backup_path)?,
+      None => copy(Path::new(current_path_string.as_str()), backup_path)
+        .and_then(|_size| File::open(backup_path)?.sync_all())
+        .map_err(|err| format!("Couldn't back up to {}: {}", backup_path_string, err))?,
+    }
+    let now = Local::now().naive_local();
+    for (index, (path, backup_created)) in self.backups()?.into_iter().enumerate() {
+      let expired = match self.config.backup_max_age_days {
+        Some(days) => now - backup_created > chrono::Duration::days(days as i64),
+        None => false,
+      };
+      if index >= self.config.backup_count || (index > 0 && expired) {
+        remove_file(&path).map_err(|err| format!("Couldn't remove {}: {}", path.display(), err))?;
+      }
+    }
+    return Ok(());
+  }
+  // lines written before encryption was enabled are encrypted on the way into the backup
+  fn encrypted_copy(&self, key: &EncryptionKey, backup_path: &Path) -> Result<(), String> {
+    let current_path_string = self.current_path_string();
+    let content = std::fs::read_to_string(Path::new(current_path_string.as_str()))
+      .map_err(|err| format!("Couldn't read {}: {}", current_path_string, err))?;
+    let backup = encrypt_lines(key, content.as_str())?;
+    let mut file = File::create(backup_path)
+      .map_err(|err| format!("Couldn't back up to {}: {}", backup_path.display(), err))?;
+    file
+      .write_all(backup.as_bytes())
+      .and_then(|_ok| file.sync_all())
+      .map_err(|err| format!("Couldn't back up to {}: {}", backup_path.display(), err))?;
+    return Ok(());
+  }
+  // encrypts what was written before encryption was turned on, in the journal and every backup,
+  // returns the number of files changed, the caller holds the lock
+  pub fn encrypt_plaintext(&self) -> Result<usize, String> {
+    let key = match &self.config.encryption_key {
+      Some(key) => key,
+      None => return Ok(0),
+    };
+    let mut paths: Vec<PathBuf> = self
+      .backups()?
+      .into_iter()
+      .map(|(path, _created)| path)
+      .collect();
+    for path_string in [self.current_path_string(), self.archived_path_string()] {
+      if Path::new(path_string.as_str()).exists() {
+        paths.push(PathBuf::from(path_string));
+      }
+    }
+    let mut count = 0;
+    for path in paths {
+      if Self::encrypt_file(key, &path)? {
+        count += 1;
+      }
+    }
+    self.sync_directory();
+    return Ok(count);
+  }
+  // replaced through a temp file, compressed files stay compressed, false when nothing was plain
+  pub fn encrypt_file(key: &EncryptionKey, path: &Path) -> Result<bool, String> {
+    let mut content = String::new();
+    Self::open_lines(path)
+      .and_then(|mut reader| reader.read_to_string(&mut content))
+      .map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+    if content.lines().all(|line| {
+      line.is_empty()
+        || split_seal(line)
+          .map_or(line, |(_seal, content)| content)
+          .starts_with(ENCRYPTED_PREFIX)
+    }) {
+      return Ok(false);
+    }
+    let encrypted = encrypt_lines(key, content.as_str())?;
+    let temp = PathBuf::from(format!("{}.tmp", path.display()));
+    let write_error = |err: std::io::Error| format!("Couldn't write {}: {}", temp.display(), err);
+    let file = File::create(&temp).map_err(write_error)?;
+    if Self::is_compressed(path) {
+      let mut encoder = GzEncoder::new(file, Compression::default());
+      encoder
+        .write_all(encrypted.as_bytes())
+        .and_then(|_ok| encoder.finish())
+        .and_then(|file| file.sync_all())
+        .map_err(write_error)?;
+    } else {
+      let mut file = file;
+      file
+        .write_all(encrypted.as_bytes())
+        .and_then(|_ok| file.sync_all())
+        .map_err(write_error)?;
+    }
+    rename(&temp, path).map_err(|err| format!("Couldn't replace {}: {}", path.display(), err))?;
+    return Ok(true);
+  }
+  // posting only appends, the file stays in insertion order until the next rewrite
+  fn append_entries(&self, entries: &[EntryObject]) -> Result<(), String> {
+    let current_path_string = self.current_path_string();
+    let index_path_string = self.index_path_string();
+    // an index that is already stale isn't extended, the next read rebuilds it
+    let index_stamp = IndexStamp::of(current_path_string.as_str(), 0)
+      .ok()
+      .filter(|_stamp| self.keeps_index())
+      .and_then(|current| EntryIndex::load_stamp(index_path_string.as_str(), &current));
+    let mut previous = if self.config.hash_chain {
+      self.last_seal()?
+    } else {
+      String::new()
+    };
+    let mut content = String::new();
+    let mut records: Vec<IndexRecord> = vec![];
+    for item in entries {
+      let mut line = self.converter.serialize_entry(item.clone())?;
+      if self.config.hash_chain {
+        line = seal_line(previous.as_str(), line.as_str());
+        previous = line[SEAL_PREFIX.len()..SEAL_PREFIX.len() + SEAL_HASH_LENGTH].to_string();
+      }
+      if let Some(stamp) = &index_stamp {
+        records.push(IndexRecord::new(
+          stamp.lines + records.len() + 1,
+          stamp.size + content.len() as u64,
+          line.len() as u64,
+          Some(item),
+        ));
+      }
+      content.push_str((line + "\n").as_str());
+    }
+    let mut file = OpenOptions::new()
       .create(true)
       .append(true)
-      .open(current_path)
-      .unwrap();
-    let mut writer = std::io::LineWriter::new(file);
-    for item in entries {
-      let entry_string = self.serialize_entry(item.clone())? + "\n";
-      writer.write(entry_string.as_bytes()).unwrap();
+      .open(Path::new(current_path_string.as_str()))
+      .map_err(|err| format!("Couldn't open {}: {}", current_path_string, err))?;
+    file
+      .write_all(content.as_bytes())
+      .and_then(|_ok| file.sync_data())
+      .map_err(|err| format!("Couldn't write {}: {}", current_path_string, err))?;
+    if let Some(stamp) = index_stamp {
+      if let Ok(stamp) = IndexStamp::of(current_path_string.as_str(), stamp.lines + entries.len()) {
+        let _ = EntryIndex::append(index_path_string.as_str(), &records, stamp);
+      }
     }
-    return Ok(entry);
+    return Ok(());
   }
+  fn lock_path_string(&self) -> String {
+    return lock_path_string(&self.config);
+  }
+  fn temp_path_string(&self) -> String {
+    return format!("{}.tmp", self.current_path_string());
+  }
+  fn sync_directory(&self) {
+    // not supported everywhere, the rename itself is still atomic
+    if let Ok(dir) = File::open(Path::new(self.config.file_path.as_str())) {
+      let _ = dir.sync_all();
+    }
+  }
+  // writes a temp file, keeps the current file as backup and swaps them
+  fn replace_current(&self, content: &str) -> Result<(), String> {
+    let current_path_string = self.current_path_string();
+    let temp_path_string = self.temp_path_string();
+    let current_path = Path::new(current_path_string.as_str());
+    let temp_path = Path::new(temp_path_string.as_str());
+    let mut file = File::create(temp_path)
+      .map_err(|err| format!("Couldn't create {}: {}", temp_path_string, err))?;
+    file
+      .write_all(content.as_bytes())
+      .and_then(|_ok| file.sync_all())
+      .map_err(|err| format!("Couldn't write {}: {}", temp_path_string, err))?;
+    if current_path.exists() {
+      self.rotate_backups()?;
+    }
+    rename(temp_path, current_path)
+      .map_err(|err| format!("Couldn't replace {}: {}", current_path_string, err))?;
+    self.sync_directory();
+    return Ok(());
+  }
+  // stops at the first line that isn't sealed or doesn't match the line before it, the caller
+  // holds the lock
+  fn chain_report(&self) -> Result<ChainReport, String> {
+    let path = self.current_path_string();
+    let mut reader = self.current_lines()?;
+    let mut report = ChainReport {
+      path: path.clone(),
+      lines: 0,
+      last_hash: None,
+      first_broken: None,
+    };
+    let mut line = 0;
+    loop {
+      let mut raw: Vec<u8> = vec![];
+      if reader
+        .read_until(b'\n', &mut raw)
+        .map_err(|err| format!("Couldn't read {}: {}", path, err))?
+        == 0
+      {
+        break;
+      }
+      if raw.last() == Some(&b'\n') {
+        raw.pop();
+      }
+      line += 1;
+      if raw.is_empty() {
+        continue;
+      }
+      let text = String::from_utf8_lossy(&raw).to_string();
+      let previous = report.last_hash.clone().unwrap_or_default();
+      let reason = match split_seal(text.as_str()) {
+        None => "Line isn't sealed",
+        Some((seal, content)) if seal != chain_hash(previous.as_str(), content) => {
+          "Hash doesn't match, this line or the one before it was changed or removed"
+        }
+        Some((seal, _content)) => {
+          report.lines += 1;
+          report.last_hash = Some(seal.to_string());
+          continue;
+        }
+      };
+      report.first_broken = Some(LoadDiagnostic {
+        line,
+        raw: text,
+        reason: reason.to_string(),
+      });
+      break;
+    }
+    return Ok(report);
+  }
+  // the sorted entries replace the journal, the index is written along with it, a broken chain
+  // isn't sealed over so it stays visible to verify
+  fn rewrite_entries(&self, mut entries: Vec<EntryObject>) -> Result<(), String> {
+    if self.config.hash_chain {
+      if let Some(broken) = self.chain_report()?.first_broken {
+        return Err(format!(
+          "The hash chain of {} is broken at line {}: {}, run entrylogger verify first",
+          self.current_path_string(),
+          broken.line,
+          broken.reason
+        ));
+      }
+    }
+    entries.sort();
+    let mut content = String::new();
+    let mut records: Vec<IndexRecord> = vec![];
+    let mut previous = String::new();
+    for item in &entries {
+      let mut line = self.converter.serialize_entry(item.clone())?;
+      if self.config.hash_chain {
+        line = seal_line(previous.as_str(), line.as_str());
+        previous = line[SEAL_PREFIX.len()..SEAL_PREFIX.len() + SEAL_HASH_LENGTH].to_string();
+      }
+      records.push(IndexRecord::new(
+        records.len() + 1,
+        content.len() as u64,
+        line.len() as u64,
+        Some(item),
+      ));
+      content.push_str((line + "\n").as_str());
+    }
+    self.replace_current(content.as_str())?;
+    if !self.keeps_index() {
+      return Ok(());
+    }
+    if let Ok(stamp) = IndexStamp::of(self.current_path_string().as_str(), records.len()) {
+      let _ = EntryIndex { records, stamp }.write(self.index_path_string().as_str());
+    }
+    return Ok(());
+  }
+  // the incomplete last line and the offset it starts at, None when the journal ends with a newline
+  fn torn_tail(&self) -> Result<Option<(u64, Vec<u8>)>, String> {
+    let path = self.current_path_string();
+    if !Path::new(path.as_str()).exists() {
+      return Ok(None);
+    }
+    let read_error = |err: std::io::Error| format!("Couldn't read {}: {}", path, err);
+    let file = File::open(Path::new(path.as_str())).map_err(read_error)?;
+    let mut lines = ReverseLines::new(file).map_err(read_error)?;
+    match lines.next_line().map_err(read_error)? {
+      Some((offset, raw)) if !raw.is_empty() => return Ok(Some((offset, raw))),
+      _ => return Ok(None),
+    }
+  }
+  fn needs_recovery(&self) -> Result<bool, String> {
+    let current_exists = Path::new(self.current_path_string().as_str()).exists();
+    let archived_exists = Path::new(self.archived_path_string().as_str()).exists();
+    return Ok(
+      Path::new(self.temp_path_string().as_str()).exists()
+        || (current_exists && archived_exists)
+        || (!current_exists && !archived_exists && !self.backups()?.is_empty())
+        || (!self.keeps_index() && Path::new(self.index_path_string().as_str()).exists())
+        || self.torn_tail()?.is_some(),
+    );
+  }
+  // repairs what an interrupted write leaves behind, returns what was done
+  pub fn recover(&self) -> Result<Vec<String>, String> {
+    // runs on every start, a journal that needs nothing only costs a shared lock and its last line
+    {
+      let _lock = self.lock(false)?;
+      if !self.needs_recovery()? {
+        return Ok(vec![]);
+      }
+    }
+    let _lock = self.lock(true)?;
+    let mut messages: Vec<String> = vec![];
+    let current_path_string = self.current_path_string();
+    let temp_path_string = self.temp_path_string();
+    let current_path = Path::new(current_path_string.as_str());
+    let temp_path = Path::new(temp_path_string.as_str());
+    if temp_path.exists() {
+      remove_file(temp_path)
+        .map_err(|err| format!("Couldn't remove {}: {}", temp_path_string, err))?;
+      messages.push(format!(
+        "Discarded unfinished rewrite {}, {} was left untouched",
+        temp_path_string, current_path_string
+      ));
+    }
+    let archived_path_string = self.archived_path_string();
+    let archived_path = Path::new(archived_path_string.as_str());
+    if current_path.exists() && archived_path.exists() {
+      remove_file(archived_path)
+        .map_err(|err| format!("Couldn't remove {}: {}", archived_path_string, err))?;
+      messages.push(format!(
+        "Removed {}, {} is the newer copy",
+        archived_path_string, current_path_string
+      ));
+    }
+    if !current_path.exists() && !archived_path.exists() {
+      if let Some((backup_path, _created)) = self.backups()?.first() {
+        let mut content: Vec<u8> = vec![];
+        Self::open_lines(backup_path)
+          .and_then(|mut reader| reader.read_to_end(&mut content))
+          .and_then(|_size| std::fs::write(current_path, content))
+          .map_err(|err| format!("Couldn't restore {}: {}", backup_path.display(), err))?;
+        messages.push(format!(
+          "{} was missing, restored it from {}",
+          current_path_string,
+          backup_path.display()
+        ));
+      }
+    }
+    let index_path_string = self.index_path_string();
+    if !self.keeps_index() && Path::new(index_path_string.as_str()).exists() {
+      remove_file(Path::new(index_path_string.as_str()))
+        .map_err(|err| format!("Couldn't remove {}: {}", index_path_string, err))?;
+      messages.push(format!(
+        "Removed {}, {} journals aren't indexed",
+        index_path_string,
+        if self.config.encryption_key.is_some() {
+          "encrypted"
+        } else {
+          "archived"
+        }
+      ));
+    }
+    if let Some((keep, tail)) = self.torn_tail()? {
+      // a complete entry without newline, e.g. saved by an editor, only lacks the newline
+      let complete = String::from_utf8(tail.clone())
+        .ok()
+        .is_some_and(|tail| self.parse_line(tail).is_ok());
+      if complete {
+        let mut file = OpenOptions::new()
+          .append(true)
+          .open(current_path)
+          .map_err(|err| format!("Couldn't open {}: {}", current_path_string, err))?;
+        file
+          .write_all(b"\n")
+          .and_then(|_ok| file.sync_all())
+          .map_err(|err| format!("Couldn't write {}: {}", current_path_string, err))?;
+        messages.push(format!(
+          "Added the missing newline at the end of {}",
+          current_path_string
+        ));
+        return Ok(messages);
+      }
+      let file = OpenOptions::new()
+        .write(true)
+        .open(current_path)
+        .map_err(|err| format!("Couldn't open {}: {}", current_path_string, err))?;
+      file
+        .set_len(keep)
+        .and_then(|_ok| file.sync_all())
+        .map_err(|err| format!("Couldn't truncate {}: {}", current_path_string, err))?;
+      messages.push(format!(
+        "Removed incomplete last line of {}: {}",
+        current_path_string,
+        String::from_utf8_lossy(&tail)
+      ));
+    }
+    return Ok(messages);
+  }
+}
+
+static BACKUP_TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S%3f";
+pub static ARCHIVE_EXTENSION: &str = ".gz";
+
+const REVERSE_CHUNK_SIZE: u64 = 8192;
+
+// lines of a file from the last to the first with the offset they start at, memory is
+// bounded by the chunk size and the longest line
+struct ReverseLines {
+  file: File,
+  position: u64,
+  pending: Vec<u8>,
 }
 
-trait EntryStringConverter {
+impl ReverseLines {
+  fn new(file: File) -> std::io::Result<ReverseLines> {
+    let position = file.metadata()?.len();
+    return Ok(ReverseLines {
+      file,
+      position,
+      pending: vec![],
+    });
+  }
+  fn next_line(&mut self) -> std::io::Result<Option<(u64, Vec<u8>)>> {
+    loop {
+      if let Some(index) = self.pending.iter().rposition(|&byte| byte == b'\n') {
+        let line = self.pending.split_off(index + 1);
+        self.pending.truncate(index);
+        return Ok(Some((self.position + index as u64 + 1, line)));
+      }
+      if self.position == 0 {
+        if self.pending.is_empty() {
+          return Ok(None);
+        }
+        return Ok(Some((0, std::mem::take(&mut self.pending))));
+      }
+      let start = self.position.saturating_sub(REVERSE_CHUNK_SIZE);
+      let mut chunk = vec![0; (self.position - start) as usize];
+      self.file.seek(SeekFrom::Start(start))?;
+      self.file.read_exact(&mut chunk)?;
+      chunk.append(&mut self.pending);
+      self.pending = chunk;
+      self.position = start;
+    }
+  }
+}
+
+pub trait EntryStringConverter {
   fn parse_string(&self, entry_string: String) -> Result<EntryObject, String>;
   fn serialize_entry(&self, entry: EntryObject) -> Result<String, String>;
 }
 
+#[derive(Debug, Clone)]
 pub struct FileSystemConfiguration {
   pub file_name: String,
   pub file_path: String,
   pub file_current_extension: String,
   pub file_backup_extension: String,
+  pub lock_timeout_ms: u64,
+  pub load_mode: LoadMode,
+  pub format_version: u8,
+  pub backup_count: usize,
+  pub backup_max_age_days: Option<u64>,
+  pub shard_by: Option<ShardPeriod>,
+  pub hash_chain: bool,
+  pub encryption_key: Option<EncryptionKey>,
+}
+
+impl Default for FileSystemConfiguration {
+  fn default() -> FileSystemConfiguration {
+    return FileSystemConfiguration {
+      file_name: "entries".to_owned(),
+      file_path: "./".to_owned(),
+      file_current_extension: ".log".to_owned(),
+      file_backup_extension: ".bak".to_owned(),
+      lock_timeout_ms: 5000,
+      load_mode: LoadMode::Strict,
+      format_version: 2,
+      backup_count: 5,
+      backup_max_age_days: None,
+      shard_by: None,
+      hash_chain: false,
+      encryption_key: None,
+    };
+  }
+}
+
+// also held while files next to the journal are created, like the key check
+pub fn lock_path_string(config: &FileSystemConfiguration) -> String {
+  return format!(
+    "{}/{}{}.lock",
+    config.file_path.to_owned(),
+    config.file_name.to_owned(),
+    config.file_current_extension.to_owned()
+  );
 }
 
 pub trait FileSystemLoader {
   fn load(config: FileSystemConfiguration) -> Self;
 }
 
-impl EntryStringConverter for FileSystemPersistence {
+const V2_MARKER: &str = "v2 ";
+
+// v2 escapes with backslashes: \\ \n \r everywhere, \[ \] \: \s (space) in metadata values
+fn escape_field(value: &str, metadata: bool) -> String {
+  let mut escaped = String::new();
+  for character in value.chars() {
+    match character {
+      '\\' => escaped.push_str("\\\\"),
+      '\n' => escaped.push_str("\\n"),
+      '\r' => escaped.push_str("\\r"),
+      ' ' if metadata => escaped.push_str("\\s"),
+      '[' | ']' | ':' if metadata => {
+        escaped.push('\\');
+        escaped.push(character);
+      }
+      _ => escaped.push(character),
+    }
+  }
+  return escaped;
+}
+
+fn unescape_field(value: &str) -> Result<String, String> {
+  let mut unescaped = String::new();
+  let mut characters = value.chars();
+  while let Some(character) = characters.next() {
+    if character != '\\' {
+      unescaped.push(character);
+      continue;
+    }
+    match characters.next() {
+      Some('n') => unescaped.push('\n'),
+      Some('r') => unescaped.push('\r'),
+      Some('s') => unescaped.push(' '),
+      Some(other) => unescaped.push(other),
+      None => return Err(format!("Dangling escape at the end of: {}", value)),
+    }
+  }
+  return Ok(unescaped);
+}
+
+// byte positions of every separator that isn't escaped
+fn find_unescaped(value: &str, separator: char) -> Vec<usize> {
+  let mut positions: Vec<usize> = vec![];
+  let mut escaped = false;
+  for (index, character) in value.char_indices() {
+    if escaped {
+      escaped = false;
+    } else if character == '\\' {
+      escaped = true;
+    } else if character == separator {
+      positions.push(index);
+    }
+  }
+  return positions;
+}
+
+fn split_unescaped(value: &str, separator: char) -> Vec<&str> {
+  let mut parts: Vec<&str> = vec![];
+  let mut start = 0;
+  for index in find_unescaped(value, separator) {
+    parts.push(&value[start..index]);
+    start = index + separator.len_utf8();
+  }
+  parts.push(&value[start..]);
+  return parts;
+}
+
+static SEAL_PREFIX: &str = "sha256:";
+const SEAL_HASH_LENGTH: usize = 64;
+
+// links a line to the one before it: sha256 of the previous hash, a newline and the content
+fn chain_hash(previous: &str, content: &str) -> String {
+  let mut hasher = Sha256::new();
+  hasher.update(previous.as_bytes());
+  hasher.update(b"\n");
+  hasher.update(content.as_bytes());
+  return format!("{:x}", hasher.finalize());
+}
+
+// encrypts the lines that aren't yet, a line sealed in an intact chain is sealed again over its
+// new content, a broken seal is kept as it is so the break stays visible
+fn encrypt_lines(key: &EncryptionKey, content: &str) -> Result<String, String> {
+  let mut encrypted = String::new();
+  let mut previous = String::new();
+  let mut original_previous = String::new();
+  for line in content.lines() {
+    if line.is_empty() {
+      encrypted.push('\n');
+      continue;
+    }
+    let (seal, unsealed) = match split_seal(line) {
+      Some((seal, content)) => (Some(seal), content),
+      None => (None, line),
+    };
+    let mut copied = if unsealed.starts_with(ENCRYPTED_PREFIX) {
+      unsealed.to_string()
+    } else {
+      key.encrypt(unsealed)?
+    };
+    if let Some(seal) = seal {
+      if seal == chain_hash(original_previous.as_str(), unsealed) {
+        copied = seal_line(previous.as_str(), copied.as_str());
+        previous = copied[SEAL_PREFIX.len()..SEAL_PREFIX.len() + SEAL_HASH_LENGTH].to_string();
+      } else {
+        copied = format!("{}{} {}", SEAL_PREFIX, seal, copied);
+      }
+      original_previous = seal.to_string();
+    }
+    encrypted.push_str(copied.as_str());
+    encrypted.push('\n');
+  }
+  return Ok(encrypted);
+}
+
+// "sha256:<hash> <content>", the content is the line the converter wrote
+fn seal_line(previous: &str, content: &str) -> String {
+  return format!(
+    "{}{} {}",
+    SEAL_PREFIX,
+    chain_hash(previous, content),
+    content
+  );
+}
+
+// the hash and the content of a sealed line, None for lines written without hash_chain
+fn split_seal(line: &str) -> Option<(&str, &str)> {
+  let rest = line.strip_prefix(SEAL_PREFIX)?;
+  let bytes = rest.as_bytes();
+  if bytes.len() <= SEAL_HASH_LENGTH
+    || bytes[SEAL_HASH_LENGTH] != b' '
+    || !bytes[..SEAL_HASH_LENGTH]
+      .iter()
+      .all(|byte| byte.is_ascii_hexdigit())
+  {
+    return None;
+  }
+  return Some((&rest[..SEAL_HASH_LENGTH], &rest[SEAL_HASH_LENGTH + 1..]));
+}
+
+pub fn check_sortable(metadata: &EntryMetadata) -> Result<(), String> {
+  if chrono::NaiveDate::parse_from_str(metadata.date.as_str(), "%d-%b-%y").is_err() {
+    return Err(format!("Invalid date: {}", metadata.date));
+  }
+  if let Some(ins) = &metadata.ins {
+    if ins.parse::<i64>().is_err() {
+      return Err(format!("Invalid ins: {}", ins));
+    }
+  }
+  return Ok(());
+}
+
+// the original unversioned format and v2, selected by format_version when writing
+pub struct TextLineConverter {
+  pub format_version: u8,
+}
+
+impl TextLineConverter {
+  fn parse_string_v2(&self, entry_string: &str) -> Result<EntryObject, String> {
+    let content = match entry_string.strip_prefix('[') {
+      Some(content) => content,
+      None => return Err("Couldn't parse string to Entry: ".to_string() + entry_string),
+    };
+    let end = match find_unescaped(content, ']').first() {
+      Some(end) => *end,
+      None => return Err("Couldn't parse string to Entry: ".to_string() + entry_string),
+    };
+    let rest = &content[end + 1..];
+    let message = rest.strip_prefix(' ').unwrap_or(rest);
+    let mut metadata = EntryMetadata {
+      ins: None,
+      date: "".to_string(),
+      time: "".to_string(),
+      tag: "".to_string(),
+    };
+    for meta in split_unescaped(&content[..end], ' ') {
+      let metadata_split_value = split_unescaped(meta, ':');
+      if metadata_split_value.len() != 2 {
+        return Err(format!("Invalid metadata field: {}", meta));
+      }
+      let value = unescape_field(metadata_split_value[1])?;
+      match metadata_split_value[0] {
+        "ins" => metadata.ins = Some(value),
+        "date" => metadata.date = value,
+        "time" => metadata.time = value,
+        "tag" => metadata.tag = value,
+        _ => return Err("Invalid value detected in metadata".to_string()),
+      }
+    }
+    check_sortable(&metadata)?;
+    return Ok(EntryObject {
+      metadata,
+      message: unescape_field(message)?,
+    });
+  }
+}
+
+impl EntryStringConverter for TextLineConverter {
   fn parse_string(&self, entry_string: String) -> Result<EntryObject, String> {
     lazy_static! {
       static ref REGEX: Regex = Regex::new(r"\[(.*?)\]").unwrap();
+      static ref VERSION_REGEX: Regex = Regex::new(r"^v[0-9]+ ").unwrap();
     };
+    if let Some(rest) = entry_string.strip_prefix(V2_MARKER) {
+      return self.parse_string_v2(rest);
+    }
+    if let Some(version) = VERSION_REGEX.find(entry_string.as_str()) {
+      return Err(format!(
+        "Unsupported format version: {}",
+        version.as_str().trim_end()
+      ));
+    }
     let match_metadata = REGEX.is_match(entry_string.as_str());
     if !match_metadata {
       return Err("Couldn't parse string to Entry: ".to_string() + entry_string.as_str());
@@ -115,6 +1427,9 @@ impl EntryStringConverter for FileSystemPersistence {
     };
     for meta in metadata_split {
       let metadata_split_value: Vec<&str> = meta.split(':').collect();
+      if metadata_split_value.len() != 2 {
+        return Err(format!("Invalid metadata field: {}", meta));
+      }
       match metadata_split_value[0] {
         "ins" => metadata.ins = Some(metadata_split_value[1].to_string()),
         "date" => metadata.date = metadata_split_value[1].to_string(),
@@ -123,6 +1438,7 @@ impl EntryStringConverter for FileSystemPersistence {
         _ => return Err("Invalid value detected in metadata".to_string()),
       }
     }
+    check_sortable(&metadata)?;
     let result = EntryObject {
       metadata,
       message: message.to_string(),
@@ -130,13 +1446,25 @@ impl EntryStringConverter for FileSystemPersistence {
     return Ok(result);
   }
   fn serialize_entry(&self, entry: EntryObject) -> Result<String, String> {
+    if self.format_version == 1 {
+      let entry_string = format!(
+        "[ins:{} date:{} time:{} tag:{}] {}",
+        entry.metadata.ins.unwrap(),
+        entry.metadata.date,
+        entry.metadata.time,
+        entry.metadata.tag,
+        entry.message
+      );
+      return Ok(entry_string);
+    }
     let entry_string = format!(
-      "[ins:{} date:{} time:{} tag:{}] {}",
-      entry.metadata.ins.unwrap(),
-      entry.metadata.date,
-      entry.metadata.time,
-      entry.metadata.tag,
-      entry.message
+      "{}[ins:{} date:{} time:{} tag:{}] {}",
+      V2_MARKER,
+      escape_field(entry.metadata.ins.unwrap().as_str(), true),
+      escape_field(entry.metadata.date.as_str(), true),
+      escape_field(entry.metadata.time.as_str(), true),
+      escape_field(entry.metadata.tag.as_str(), true),
+      escape_field(entry.message.as_str(), false)
     );
     return Ok(entry_string);
   }
@@ -144,14 +1472,49 @@ impl EntryStringConverter for FileSystemPersistence {
 
 impl FileSystemLoader for FileSystemPersistence {
   fn load(config: FileSystemConfiguration) -> Self {
-    return FileSystemPersistence { config };
+    let converter = TextLineConverter {
+      format_version: config.format_version,
+    };
+    return FileSystemPersistence::with_converter(config, Box::new(converter));
+  }
+}
+
+impl FileSystemPersistence {
+  // same files, locking and backups with a different line format
+  pub fn with_converter(
+    config: FileSystemConfiguration,
+    converter: Box<dyn EntryStringConverter>,
+  ) -> FileSystemPersistence {
+    let converter: Box<dyn EntryStringConverter> = match &config.encryption_key {
+      Some(key) => Box::new(EncryptedConverter::new(converter, key.clone())),
+      None => converter,
+    };
+    return FileSystemPersistence {
+      config,
+      converter,
+      diagnostics: RefCell::new(vec![]),
+    };
   }
 }
 
 /* -----------------------------------TESTS------------------------------------------ */
 #[cfg(test)]
 mod tests {
+  use super::super::persistence_jsonlines::JsonLinesPersistence;
+  use super::super::test_support::{test_dir, test_entry};
   use super::*;
+  use std::rc::Rc;
+  fn test_persistence(name: &str) -> FileSystemPersistence {
+    let dir = test_dir(name);
+    let config = FileSystemConfiguration {
+      file_name: "entries".to_owned(),
+      file_path: dir.to_str().unwrap().to_owned(),
+      file_current_extension: ".log".to_owned(),
+      file_backup_extension: ".bak".to_owned(),
+      ..FileSystemConfiguration::default()
+    };
+    return FileSystemLoader::load(config);
+  }
   #[test]
   fn parse_string_test() {
     let string =
@@ -161,9 +1524,10 @@ mod tests {
       file_path: "./".to_owned(),
       file_current_extension: ".ok".to_owned(),
       file_backup_extension: ".bak".to_owned(),
+      ..FileSystemConfiguration::default()
     };
     let persistence: FileSystemPersistence = FileSystemLoader::load(config);
-    let object = persistence.parse_string(string).unwrap();
+    let object = persistence.converter.parse_string(string).unwrap();
     let compare_metadata = EntryMetadata {
       ins: Some("0000000000000".to_string()),
       date: "13-oct-20".to_string(),
@@ -183,6 +1547,8 @@ mod tests {
       file_path: "test".to_owned(),
       file_current_extension: "".to_owned(),
       file_backup_extension: "".to_owned(),
+      format_version: 1,
+      ..FileSystemConfiguration::default()
     };
     let persistence: FileSystemPersistence = FileSystemLoader::load(config);
     let input_metadata = EntryMetadata {
@@ -197,7 +1563,763 @@ mod tests {
     };
     let compare_string =
       "[ins:0000000000000 date:13-oct-20 time:morning tag:fit] aerobic (5/5)".to_string();
-    let string = persistence.serialize_entry(input_object).unwrap();
+    let string = persistence.converter.serialize_entry(input_object).unwrap();
     assert_eq!(string, compare_string);
   }
+  #[test]
+  fn update_entry_test() {
+    let persistence = test_persistence("update");
+    persistence
+      .write_entry(test_entry("0000000000001", "one"))
+      .unwrap();
+    persistence
+      .write_entry(test_entry("0000000000002", "two"))
+      .unwrap();
+    let updated = persistence
+      .update_entry(test_entry("0000000000001", "uno"))
+      .unwrap();
+    assert_eq!(updated.message, "uno");
+    let entries = persistence.read_entries().unwrap();
+    assert_eq!(
+      entries,
+      vec![
+        test_entry("0000000000001", "uno"),
+        test_entry("0000000000002", "two")
+      ]
+    );
+    let missing = persistence.update_entry(test_entry("0000000000003", "three"));
+    assert!(missing.is_err());
+  }
+  #[test]
+  fn delete_entries_test() {
+    let persistence = test_persistence("delete");
+    persistence
+      .write_entry(test_entry("0000000000001", "one"))
+      .unwrap();
+    persistence
+      .write_entry(test_entry("0000000000002", "two"))
+      .unwrap();
+    persistence
+      .write_entry(test_entry("0000000000003", "three"))
+      .unwrap();
+    let missing =
+      persistence.delete_entries(&["0000000000001".to_string(), "0000000000004".to_string()]);
+    assert!(missing.is_err());
+    assert_eq!(persistence.read_entries().unwrap().len(), 3);
+    let deleted = persistence
+      .delete_entries(&["0000000000001".to_string(), "0000000000003".to_string()])
+      .unwrap();
+    assert_eq!(
+      deleted,
+      vec![
+        test_entry("0000000000001", "one"),
+        test_entry("0000000000003", "three")
+      ]
+    );
+    let entries = persistence.read_entries().unwrap();
+    assert_eq!(entries, vec![test_entry("0000000000002", "two")]);
+  }
+  #[test]
+  fn write_entry_appends_test() {
+    let persistence = test_persistence("append");
+    persistence
+      .write_entry(test_entry("0000000000002", "two"))
+      .unwrap();
+    persistence
+      .write_entries(vec![
+        test_entry("0000000000003", "three"),
+        test_entry("0000000000001", "one"),
+      ])
+      .unwrap();
+    let content = std::fs::read_to_string(persistence.current_path_string()).unwrap();
+    assert_eq!(content.lines().count(), 3);
+    let entries = persistence.read_entries().unwrap();
+    assert_eq!(entries[0], test_entry("0000000000002", "two"));
+    assert_eq!(entries[2], test_entry("0000000000001", "one"));
+    assert_eq!(persistence.compact().unwrap(), 3);
+    let entries = persistence.read_entries().unwrap();
+    assert_eq!(entries[0], test_entry("0000000000001", "one"));
+    assert_eq!(entries[2], test_entry("0000000000003", "three"));
+
+    // a post gets an ins past the last line and not taken anywhere in the journal
+    let insert = |ins: &str| -> String {
+      return persistence
+        .insert_entry(test_entry(ins, "posted"))
+        .unwrap()
+        .metadata
+        .ins
+        .unwrap();
+    };
+    assert_eq!(insert("0000000000002"), "0000000000004");
+    assert_eq!(insert("0000000000009"), "0000000000009");
+    persistence
+      .write_entries(vec![
+        test_entry("0000000000020", "imported"),
+        test_entry("0000000000010", "imported"),
+      ])
+      .unwrap();
+    assert_eq!(insert("0000000000020"), "0000000000021");
+    assert_eq!(persistence.read_entries().unwrap().len(), 8);
+  }
+  // cargo test --release -- --ignored --nocapture bench_post_latency
+  #[test]
+  #[ignore]
+  fn bench_post_latency() {
+    let persistence = test_persistence("bench");
+    let mut timings: Vec<(usize, u128)> = vec![];
+    let mut size = 0;
+    for target in [1000, 10000, 100000] {
+      let filler: Vec<EntryObject> = (size..target)
+        .map(|index| test_entry(format!("{:013}", index).as_str(), "filler"))
+        .collect();
+      persistence.write_entries(filler).unwrap();
+      size = target;
+      let posts = 100;
+      let start = std::time::Instant::now();
+      // what every entrylogger post does: load, check the journal, then validate and append
+      for _index in 0..posts {
+        let run: FileSystemPersistence = FileSystemLoader::load(persistence.config.clone());
+        run.recover().unwrap();
+        let controller = EntryController::new(Rc::new(EntryBusiness {}), Rc::new(run));
+        controller.post_entry(test_entry("", "post")).unwrap();
+      }
+      size += posts;
+      let average = start.elapsed().as_micros() / posts as u128;
+      println!("{} entries: {} us per post", target, average);
+      timings.push((target, average));
+    }
+    // a 100x larger file must not make posting anywhere near 100x slower
+    assert!(timings[2].1 < (timings[0].1 + 50) * 5);
+  }
+  #[test]
+  fn rewrite_without_log_test() {
+    let persistence = test_persistence("rewrite");
+    persistence
+      .update_entry(test_entry("0000000000001", "one"))
+      .unwrap_err();
+    assert_eq!(persistence.compact().unwrap(), 0);
+    std::fs::remove_file(persistence.current_path_string()).unwrap();
+    for (path, _created) in persistence.backups().unwrap() {
+      std::fs::remove_file(path).unwrap();
+    }
+    assert!(persistence.rewrite_entries(vec![]).is_ok());
+    assert!(!Path::new(persistence.temp_path_string().as_str()).exists());
+  }
+  #[test]
+  fn recover_test() {
+    let mut persistence = test_persistence("recover");
+    persistence.config.lock_timeout_ms = 50;
+    assert_eq!(persistence.recover().unwrap().len(), 0);
+    persistence
+      .write_entry(test_entry("0000000000001", "one"))
+      .unwrap();
+    persistence.compact().unwrap();
+    // interrupted rewrite
+    std::fs::write(persistence.temp_path_string(), "[ins:00").unwrap();
+    assert_eq!(persistence.recover().unwrap().len(), 1);
+    assert!(!Path::new(persistence.temp_path_string().as_str()).exists());
+    // missing log
+    std::fs::remove_file(persistence.current_path_string()).unwrap();
+    assert_eq!(persistence.recover().unwrap().len(), 1);
+    assert_eq!(
+      persistence.read_entries().unwrap(),
+      vec![test_entry("0000000000001", "one")]
+    );
+    // torn append
+    let mut file = OpenOptions::new()
+      .append(true)
+      .open(persistence.current_path_string())
+      .unwrap();
+    file.write_all(b"[ins:0000000000002 date:13-o").unwrap();
+    assert_eq!(persistence.recover().unwrap().len(), 1);
+    assert_eq!(
+      persistence.read_entries().unwrap(),
+      vec![test_entry("0000000000001", "one")]
+    );
+    assert_eq!(persistence.recover().unwrap().len(), 0);
+    // an intact journal is checked next to other readers
+    let reader = persistence.lock(false).unwrap();
+    assert_eq!(persistence.recover(), Ok(vec![]));
+    drop(reader);
+    // a complete last line without newline is kept
+    let content = std::fs::read_to_string(persistence.current_path_string()).unwrap();
+    std::fs::write(
+      persistence.current_path_string(),
+      content.trim_end_matches('\n'),
+    )
+    .unwrap();
+    assert_eq!(persistence.recover().unwrap().len(), 1);
+    assert_eq!(
+      std::fs::read_to_string(persistence.current_path_string()).unwrap(),
+      content
+    );
+    assert_eq!(
+      persistence.read_entries().unwrap(),
+      vec![test_entry("0000000000001", "one")]
+    );
+  }
+  #[test]
+  fn lock_timeout_test() {
+    let mut persistence = test_persistence("lock");
+    persistence.config.lock_timeout_ms = 50;
+    let exclusive = persistence.lock(true).unwrap();
+    assert!(persistence.read_entries().is_err());
+    assert!(persistence
+      .write_entry(test_entry("0000000000001", "one"))
+      .is_err());
+    drop(exclusive);
+    let shared = persistence.lock(false).unwrap();
+    assert!(persistence.read_entries().is_ok());
+    assert!(persistence
+      .write_entry(test_entry("0000000000001", "one"))
+      .is_err());
+    drop(shared);
+    assert!(persistence
+      .write_entry(test_entry("0000000000001", "one"))
+      .is_ok());
+  }
+  #[test]
+  fn load_mode_test() {
+    let mut persistence = test_persistence("lenient");
+    persistence
+      .write_entry(test_entry("0000000000001", "one"))
+      .unwrap();
+    let mut file = OpenOptions::new()
+      .append(true)
+      .open(persistence.current_path_string())
+      .unwrap();
+    file
+      .write_all(b"not an entry\n[ins:0000000000002 date:31-feb-20 time:morning tag:fit] x\n")
+      .unwrap();
+    persistence
+      .write_entry(test_entry("0000000000003", "three"))
+      .unwrap();
+    let strict = persistence.read_entries().unwrap_err();
+    assert!(strict.starts_with("Line 2 of "));
+    assert_eq!(persistence.take_diagnostics().len(), 0);
+
+    persistence.config.load_mode = LoadMode::Lenient;
+    let entries = persistence.read_entries().unwrap();
+    assert_eq!(
+      entries,
+      vec![
+        test_entry("0000000000001", "one"),
+        test_entry("0000000000003", "three")
+      ]
+    );
+    let diagnostics = persistence.take_diagnostics();
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].line, 2);
+    assert_eq!(diagnostics[0].raw, "not an entry");
+    assert_eq!(diagnostics[1].line, 3);
+    assert_eq!(diagnostics[1].reason, "Invalid date: 31-feb-20");
+    assert_eq!(persistence.take_diagnostics().len(), 0);
+    // rewriting would drop the skipped lines
+    assert!(persistence.compact().is_err());
+  }
+  #[test]
+  fn parse_string_v2_test() {
+    let persistence = test_persistence("v2");
+    let string =
+      "v2 [ins:0000000000000 date:13-oct-20 time:n/a tag:a\\:b\\]\\s\\\\] one ] two\\nthree\\\\n";
+    let object = persistence
+      .converter
+      .parse_string(string.to_string())
+      .unwrap();
+    assert_eq!(object.metadata.tag, "a:b] \\");
+    assert_eq!(object.metadata.time, "n/a");
+    assert_eq!(object.message, "one ] two\nthree\\n");
+    assert_eq!(
+      persistence.converter.serialize_entry(object).unwrap(),
+      string.to_string()
+    );
+    let unsupported = persistence
+      .converter
+      .parse_string("v3 [ins:0000000000000] x".to_string());
+    assert_eq!(
+      unsupported,
+      Err("Unsupported format version: v3".to_string())
+    );
+    let dangling = persistence
+      .converter
+      .parse_string("v2 [ins:0000000000000 date:13-oct-20 time:n/a tag:fit] x\\".to_string());
+    assert!(dangling.is_err());
+  }
+  #[test]
+  fn round_trip_message_characters_test() {
+    let persistence = test_persistence("roundtrip");
+    let entry_business = EntryBusiness {};
+    let allowed: String = (0u8..128)
+      .map(|byte| byte as char)
+      .filter(|character| {
+        entry_business
+          .validate_message(character.to_string().as_str())
+          .is_ok()
+      })
+      .collect();
+    assert!(allowed.contains(']'));
+    assert!(allowed.contains('\\'));
+    assert!(allowed.contains(':'));
+    let mut messages: Vec<String> = allowed
+      .chars()
+      .map(|character| character.to_string())
+      .collect();
+    messages.push(allowed.clone());
+    messages.push("] [ins:1 date:x] \\".to_string());
+    messages.push("line one\nline two\r\n".to_string());
+    for message in messages {
+      let mut entry = test_entry("0000000000001", message.as_str());
+      entry.metadata.tag = format!("t:{}", message);
+      let string = persistence
+        .converter
+        .serialize_entry(entry.clone())
+        .unwrap();
+      assert!(!string.contains('\n'));
+      assert_eq!(persistence.converter.parse_string(string).unwrap(), entry);
+    }
+  }
+  #[test]
+  fn mixed_versions_test() {
+    let mut persistence = test_persistence("mixed");
+    persistence.converter = Box::new(TextLineConverter { format_version: 1 });
+    persistence
+      .write_entry(test_entry("0000000000001", "one"))
+      .unwrap();
+    persistence.converter = Box::new(TextLineConverter { format_version: 2 });
+    persistence
+      .write_entry(test_entry("0000000000002", "two: [2]"))
+      .unwrap();
+    let content = std::fs::read_to_string(persistence.current_path_string()).unwrap();
+    assert!(content.lines().next().unwrap().starts_with('['));
+    assert!(content.lines().nth(1).unwrap().starts_with("v2 ["));
+    assert_eq!(
+      persistence.read_entries().unwrap(),
+      vec![
+        test_entry("0000000000001", "one"),
+        test_entry("0000000000002", "two: [2]")
+      ]
+    );
+  }
+  #[test]
+  fn backup_retention_test() {
+    let mut persistence = test_persistence("backups");
+    persistence.config.backup_count = 3;
+    persistence
+      .write_entry(test_entry("0000000000001", "one"))
+      .unwrap();
+    let legacy = persistence.legacy_backup_path_string();
+    std::fs::write(&legacy, "").unwrap();
+    let old = Local::now().naive_local() - chrono::Duration::days(40);
+    std::fs::write(persistence.backup_path_string(old), "").unwrap();
+    assert_eq!(persistence.list_backups().unwrap().len(), 2);
+    for count in 2..6 {
+      persistence
+        .write_entry(test_entry(format!("{:013}", count).as_str(), "next"))
+        .unwrap();
+      persistence.compact().unwrap();
+    }
+    // newest first, the legacy and the old backup were rotated out
+    let backups = persistence.list_backups().unwrap();
+    assert_eq!(
+      backups
+        .iter()
+        .map(|backup| backup.entries)
+        .collect::<Vec<usize>>(),
+      vec![5, 4, 3]
+    );
+    assert!(!Path::new(legacy.as_str()).exists());
+    let content = std::fs::read_to_string(&backups[0].path).unwrap();
+    assert_eq!(backups[0].size, content.len() as u64);
+
+    std::fs::write(persistence.backup_path_string(old), "").unwrap();
+    persistence.config.backup_count = 10;
+    persistence.config.backup_max_age_days = Some(30);
+    persistence.compact().unwrap();
+    assert_eq!(persistence.list_backups().unwrap().len(), 4);
+  }
+  #[test]
+  fn shared_directory_backups_test() {
+    let mut text = test_persistence("shared");
+    text.config.backup_count = 1;
+    let jsonl = JsonLinesPersistence::files(FileSystemConfiguration {
+      file_current_extension: ".jsonl".to_owned(),
+      ..text.config.clone()
+    });
+    assert_ne!(text.lock_path_string(), jsonl.lock_path_string());
+    // a backup from before the extension was part of the name belongs to the text journal
+    let old = Local::now().naive_local() - chrono::Duration::days(1);
+    let unnamed = format!(
+      "{}/entries.{}.bak",
+      text.config.file_path,
+      old.format(BACKUP_TIMESTAMP_FORMAT)
+    );
+    std::fs::write(
+      &unnamed,
+      "v2 [ins:0000000000009 date:13-oct-20 time:morning tag:fit] old\n",
+    )
+    .unwrap();
+    for persistence in [&text, &jsonl] {
+      persistence
+        .write_entry(test_entry("0000000000001", "one"))
+        .unwrap();
+      persistence.compact().unwrap();
+      persistence.compact().unwrap();
+    }
+    let text_backups = text.list_backups().unwrap();
+    let jsonl_backups = jsonl.list_backups().unwrap();
+    assert_eq!(text_backups.len(), 1);
+    assert_eq!(jsonl_backups.len(), 1);
+    assert!(text_backups[0].path.contains("entries.log."));
+    assert!(jsonl_backups[0].path.contains("entries.jsonl."));
+    // rotated out by the text journal only
+    assert!(!Path::new(unnamed.as_str()).exists());
+  }
+  #[test]
+  fn for_each_entry_test() {
+    let mut persistence = test_persistence("stream");
+    let long = "x".repeat(REVERSE_CHUNK_SIZE as usize + 100);
+    persistence
+      .write_entries(vec![
+        test_entry("0000000000003", "three"),
+        test_entry("0000000000001", long.as_str()),
+        test_entry("0000000000002", "two"),
+      ])
+      .unwrap();
+    let visit = |persistence: &FileSystemPersistence, reverse: bool, limit: usize| {
+      let mut seen: Vec<String> = vec![];
+      persistence
+        .for_each_entry(reverse, &mut |entry| {
+          seen.push(entry.metadata.ins.unwrap());
+          return seen.len() < limit;
+        })
+        .map(|_ok| seen)
+    };
+    assert_eq!(
+      visit(&persistence, false, 10).unwrap(),
+      vec!["0000000000003", "0000000000001", "0000000000002"]
+    );
+    assert_eq!(
+      visit(&persistence, true, 10).unwrap(),
+      vec!["0000000000002", "0000000000001", "0000000000003"]
+    );
+    assert_eq!(visit(&persistence, true, 2).unwrap().len(), 2);
+
+    // the broken line is only reached when reading goes that far
+    let mut file = OpenOptions::new()
+      .append(true)
+      .open(persistence.current_path_string())
+      .unwrap();
+    file.write_all(b"broken\n\n").unwrap();
+    persistence
+      .write_entry(test_entry("0000000000004", "four"))
+      .unwrap();
+    assert_eq!(visit(&persistence, true, 1).unwrap(), vec!["0000000000004"]);
+    let strict = visit(&persistence, true, 10).unwrap_err();
+    assert!(strict.starts_with("Line 4 of "));
+    persistence.config.load_mode = LoadMode::Lenient;
+    assert_eq!(visit(&persistence, true, 10).unwrap().len(), 4);
+    let diagnostics = persistence.take_diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].line, 4);
+    assert_eq!(diagnostics[0].raw, "broken");
+  }
+  #[test]
+  fn index_test() {
+    let persistence = test_persistence("index");
+    let mut entries = vec![
+      test_entry("0000000000001", "one"),
+      test_entry("0000000000002", "two"),
+      test_entry("0000000000003", "three"),
+    ];
+    entries[0].metadata.date = "01-jan-20".to_string();
+    entries[2].metadata.tag = "work".to_string();
+    persistence.write_entries(entries.clone()).unwrap();
+    let filter = EntryFilter {
+      date_from: chrono::NaiveDate::from_ymd_opt(2020, 10, 1),
+      ..EntryFilter::new()
+    };
+    assert_eq!(
+      persistence.query_entries(&filter).unwrap(),
+      vec![entries[1].clone(), entries[2].clone()]
+    );
+    // appends extend the index instead of invalidating it
+    persistence
+      .write_entry(test_entry("0000000000004", "four"))
+      .unwrap();
+    let path = persistence.current_path_string();
+    let index = EntryIndex::load(
+      persistence.index_path_string().as_str(),
+      &IndexStamp::of(path.as_str(), 0).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(index.records.len(), 4);
+    assert_eq!(index.records[3].ins, Some("0000000000004".to_string()));
+
+    // a broken line outside the range isn't read while the index is current
+    let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+    let content = std::fs::read_to_string(&path).unwrap();
+    let first_length = content.find('\n').unwrap();
+    let broken = "#".repeat(first_length) + &content[first_length..];
+    std::fs::write(&path, broken).unwrap();
+    File::options()
+      .write(true)
+      .open(&path)
+      .unwrap()
+      .set_modified(modified)
+      .unwrap();
+    let tag_filter = EntryFilter {
+      tag: Some("work".to_string()),
+      ..EntryFilter::new()
+    };
+    assert_eq!(
+      persistence.query_entries(&tag_filter).unwrap(),
+      vec![entries[2].clone()]
+    );
+    assert_eq!(
+      persistence.find_entry("0000000000002").unwrap(),
+      Some(entries[1].clone())
+    );
+    assert_eq!(persistence.find_entry("0000000000009").unwrap(), None);
+    // once the mtime moves on the index is rebuilt and the broken line is read again
+    File::options()
+      .write(true)
+      .open(&path)
+      .unwrap()
+      .set_modified(modified + std::time::Duration::from_secs(1))
+      .unwrap();
+    let err = persistence.query_entries(&tag_filter).unwrap_err();
+    assert!(err.starts_with("Line 1 of "));
+  }
+  #[test]
+  fn hash_chain_test() {
+    let mut persistence = test_persistence("chain");
+    persistence
+      .write_entry(test_entry("0000000000001", "unsealed"))
+      .unwrap();
+    assert!(persistence.reseal_chain().is_err());
+    persistence.config.hash_chain = true;
+    let reports = persistence.verify_chain().unwrap();
+    assert_eq!(reports[0].first_broken.as_ref().unwrap().line, 1);
+    assert_eq!(persistence.reseal_chain().unwrap(), 1);
+    persistence
+      .write_entries(vec![
+        test_entry("0000000000002", "two"),
+        test_entry("0000000000003", "three"),
+      ])
+      .unwrap();
+    let report = persistence.verify_chain().unwrap().remove(0);
+    assert_eq!(report.lines, 3);
+    assert_eq!(report.first_broken, None);
+    // sealed lines read like any other
+    assert_eq!(persistence.read_entries().unwrap().len(), 3);
+    assert_eq!(
+      persistence.find_entry("0000000000002").unwrap(),
+      Some(test_entry("0000000000002", "two"))
+    );
+
+    // a silent edit breaks the link of the edited line
+    let path = persistence.current_path_string();
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, content.replace("two", "TWO")).unwrap();
+    let broken = persistence.verify_chain().unwrap()[0]
+      .first_broken
+      .clone()
+      .unwrap();
+    assert_eq!(broken.line, 2);
+    assert!(broken.raw.ends_with("TWO"));
+    // a removed line breaks the link of the line after it
+    let lines: Vec<&str> = content.lines().collect();
+    std::fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+    let broken = persistence.verify_chain().unwrap()[0]
+      .first_broken
+      .clone()
+      .unwrap();
+    assert_eq!(broken.line, 2);
+    // rewrites refuse to seal over it
+    let removed = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(
+      persistence.compact(),
+      Err(format!(
+        "The hash chain of {} is broken at line 2: {}, run entrylogger verify first",
+        path, broken.reason
+      ))
+    );
+    assert!(persistence
+      .delete_entries(&["0000000000001".to_string()])
+      .is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), removed);
+
+    assert_eq!(persistence.reseal_chain().unwrap(), 2);
+    assert_eq!(persistence.verify_chain().unwrap()[0].first_broken, None);
+    persistence.compact().unwrap();
+    let report = persistence.verify_chain().unwrap().remove(0);
+    assert_eq!((report.lines, report.first_broken), (2, None));
+  }
+  #[test]
+  fn encrypted_journal_test() {
+    let plain = test_persistence("encrypted");
+    plain
+      .write_entry(test_entry("0000000000001", "before encryption"))
+      .unwrap();
+    plain
+      .query_entries(&EntryFilter {
+        tag: Some("fit".to_string()),
+        ..EntryFilter::new()
+      })
+      .unwrap();
+    let index_path = plain.index_path_string();
+    assert!(Path::new(index_path.as_str()).exists());
+
+    let key = EncryptionKey::derive("correct horse", b"0123456789abcdef").unwrap();
+    let persistence: FileSystemPersistence = FileSystemLoader::load(FileSystemConfiguration {
+      encryption_key: Some(key),
+      ..plain.config.clone()
+    });
+    assert_eq!(
+      persistence.recover().unwrap(),
+      vec![format!(
+        "Removed {}, encrypted journals aren't indexed",
+        index_path
+      )]
+    );
+    assert!(!Path::new(index_path.as_str()).exists());
+    persistence
+      .write_entry(test_entry("0000000000002", "secret"))
+      .unwrap();
+    let path = persistence.current_path_string();
+    assert!(!std::fs::read_to_string(&path).unwrap().contains("secret"));
+    assert_eq!(persistence.read_entries().unwrap().len(), 2);
+    assert_eq!(
+      persistence.find_entry("0000000000002").unwrap(),
+      Some(test_entry("0000000000002", "secret"))
+    );
+
+    // the rewrite and its backup leave nothing in plain text
+    persistence.compact().unwrap();
+    let journal = std::fs::read_to_string(&path).unwrap();
+    let backup = std::fs::read_to_string(&persistence.list_backups().unwrap()[0].path).unwrap();
+    for content in [journal, backup] {
+      assert!(!content.contains("before encryption"));
+      assert!(!content.contains("0000000000001"));
+      assert!(content
+        .lines()
+        .all(|line| line.starts_with(ENCRYPTED_PREFIX)));
+    }
+    assert!(!Path::new(index_path.as_str()).exists());
+    // without the key the journal can't be read
+    assert!(plain.read_entries().is_err());
+
+    // lines encrypted on the way into the backup are sealed again
+    let mut sealed = test_persistence("encrypted-sealed");
+    sealed.config.hash_chain = true;
+    sealed
+      .write_entry(test_entry("0000000000001", "before encryption"))
+      .unwrap();
+    let persistence: FileSystemPersistence = FileSystemLoader::load(FileSystemConfiguration {
+      encryption_key: persistence.config.encryption_key.clone(),
+      ..sealed.config.clone()
+    });
+    persistence.compact().unwrap();
+    let backup = std::fs::read_to_string(&persistence.list_backups().unwrap()[0].path).unwrap();
+    let mut previous = String::new();
+    for line in backup.lines() {
+      let (seal, content) = split_seal(line).unwrap();
+      assert!(content.starts_with(ENCRYPTED_PREFIX));
+      assert_eq!(seal, chain_hash(previous.as_str(), content));
+      previous = seal.to_string();
+    }
+
+    // turning encryption on encrypts the journal, its backups and the legacy backup
+    let mut migrated = test_persistence("encrypted-migrated");
+    migrated.config.hash_chain = true;
+    migrated
+      .write_entry(test_entry("0000000000001", "before encryption"))
+      .unwrap();
+    migrated.compact().unwrap();
+    migrated
+      .write_entry(test_entry("0000000000002", "also before"))
+      .unwrap();
+    let legacy = migrated.legacy_backup_path_string();
+    std::fs::copy(migrated.current_path_string(), &legacy).unwrap();
+    let persistence: FileSystemPersistence = FileSystemLoader::load(FileSystemConfiguration {
+      encryption_key: persistence.config.encryption_key.clone(),
+      ..migrated.config.clone()
+    });
+    assert_eq!(persistence.encrypt_plaintext().unwrap(), 3);
+    let mut paths = vec![persistence.current_path_string(), legacy];
+    paths.extend(
+      persistence
+        .list_backups()
+        .unwrap()
+        .into_iter()
+        .map(|backup| backup.path),
+    );
+    for path in paths {
+      let content = std::fs::read_to_string(&path).unwrap();
+      assert!(!content.contains("before"));
+    }
+    assert_eq!(persistence.read_entries().unwrap().len(), 2);
+    let report = persistence.verify_chain().unwrap().remove(0);
+    assert_eq!((report.lines, report.first_broken), (2, None));
+    // nothing is left to encrypt the second time
+    assert_eq!(persistence.encrypt_plaintext().unwrap(), 0);
+  }
+  #[test]
+  fn archive_test() {
+    let persistence = test_persistence("archive");
+    persistence
+      .write_entries(vec![
+        test_entry("0000000000001", "one"),
+        test_entry("0000000000002", "two"),
+      ])
+      .unwrap();
+    persistence.compact().unwrap();
+    persistence.compact().unwrap();
+    let archived = persistence.archive().unwrap();
+    assert_eq!(archived.len(), 2);
+    assert!(archived.iter().all(|path| path.ends_with(".bak.gz")));
+    let backups = persistence.list_backups().unwrap();
+    assert_eq!(
+      backups
+        .iter()
+        .map(|backup| backup.entries)
+        .collect::<Vec<usize>>(),
+      vec![2, 2]
+    );
+
+    // every read goes through the compressed journal
+    assert_eq!(
+      persistence.archive_journal().unwrap(),
+      Some(persistence.archived_path_string())
+    );
+    let path = persistence.current_path_string();
+    assert!(!Path::new(path.as_str()).exists());
+    assert_eq!(persistence.read_entries().unwrap().len(), 2);
+    let mut newest: Vec<String> = vec![];
+    persistence
+      .for_each_entry(true, &mut |entry| {
+        newest.push(entry.message);
+        return false;
+      })
+      .unwrap();
+    assert_eq!(newest, vec!["two"]);
+    assert_eq!(
+      persistence.find_entry("0000000000001").unwrap(),
+      Some(test_entry("0000000000001", "one"))
+    );
+    persistence.compact().unwrap();
+    assert!(persistence.is_archived());
+    // writing decompresses it again
+    persistence
+      .write_entry(test_entry("0000000000003", "three"))
+      .unwrap();
+    assert!(!persistence.is_archived());
+    assert_eq!(persistence.read_entries().unwrap().len(), 3);
+
+    // a missing journal is restored from the newest backup even when it's compressed
+    persistence.archive().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(persistence.recover().unwrap().len(), 1);
+    assert_eq!(persistence.read_entries().unwrap().len(), 2);
+  }
 }
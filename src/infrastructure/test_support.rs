@@ -0,0 +1,29 @@
+use std::path::PathBuf;
+
+use domain::*;
+
+// an empty directory per test name and process, left behind for inspection
+pub fn test_dir(name: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("entrylogger-{}-{}", name, std::process::id()));
+  let _ = std::fs::remove_dir_all(&dir);
+  std::fs::create_dir_all(&dir).unwrap();
+  return dir;
+}
+
+// a fit entry in the morning of 13-oct-20
+pub fn test_entry(ins: &str, message: &str) -> EntryObject {
+  return test_entry_with(ins, "13-oct-20", "fit", message);
+}
+
+pub fn test_entry_with(ins: &str, date: &str, tag: &str, message: &str) -> EntryObject {
+  let metadata = EntryMetadata {
+    ins: Some(ins.to_string()),
+    date: date.to_string(),
+    time: "morning".to_string(),
+    tag: tag.to_string(),
+  };
+  return EntryObject {
+    metadata,
+    message: message.to_string(),
+  };
+}
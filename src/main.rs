@@ -1,10 +1,27 @@
+extern crate argon2;
+extern crate base64;
+extern crate chacha20poly1305;
 extern crate chrono;
 extern crate clap;
+extern crate csv;
+extern crate flate2;
 extern crate regex;
+extern crate rpassword;
+extern crate rusqlite;
+extern crate sha2;
 #[macro_use]
 extern crate lazy_static;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+// every layer spells out its returns, the style the code was started in
+#[allow(clippy::needless_return)]
 mod application;
+#[allow(clippy::needless_return)]
 mod domain;
+#[allow(clippy::needless_return)]
 mod infrastructure;
 
 fn main() {
@@ -0,0 +1,27 @@
+// written like the crate, with explicit returns
+#![allow(clippy::needless_return)]
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// helpers shared by the tests that run the binary
+pub fn journal_dir(name: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("entrylogger-{}-{}", name, std::process::id()));
+  let _ = std::fs::remove_dir_all(&dir);
+  std::fs::create_dir_all(&dir).unwrap();
+  return dir;
+}
+
+pub fn entrylogger(dir: &PathBuf, args: &[&str]) -> Output {
+  return Command::new(env!("CARGO_BIN_EXE_entrylogger"))
+    .args(args)
+    .arg("--file-path")
+    .arg(dir)
+    .env("XDG_CONFIG_HOME", dir)
+    .env_remove("ENTRYLOGGER_CONFIG")
+    .env_remove("ENTRYLOGGER_BACKEND")
+    .env_remove("ENTRYLOGGER_FILE_PATH")
+    .env_remove("ENTRYLOGGER_FILE_NAME")
+    .output()
+    .unwrap();
+}
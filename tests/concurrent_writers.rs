@@ -0,0 +1,72 @@
+mod common;
+
+use std::thread;
+
+use common::{entrylogger, journal_dir};
+
+const WRITERS: usize = 6;
+const POSTS_PER_WRITER: usize = 15;
+
+#[test]
+fn concurrent_posts_and_compactions_lose_no_entries() {
+  let dir = journal_dir("concurrent");
+  let mut handles = vec![];
+  for writer in 0..WRITERS {
+    let dir = dir.clone();
+    handles.push(thread::spawn(move || {
+      for post in 0..POSTS_PER_WRITER {
+        let message = format!("writer {} post {}", writer, post);
+        let output = entrylogger(
+          &dir,
+          &[
+            "-p",
+            "--date",
+            "today",
+            "--time",
+            "now",
+            "--tag",
+            "concurrent",
+            "--message",
+            message.as_str(),
+            "--no-input",
+          ],
+        );
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("Message written."), "{}", stdout);
+        // every writer also rewrites the whole journal now and then
+        if post % 5 == 4 {
+          let output = entrylogger(&dir, &["compact"]);
+          let stdout = String::from_utf8_lossy(&output.stdout);
+          assert!(stdout.contains("Compacted"), "{}", stdout);
+        }
+      }
+    }));
+  }
+  for handle in handles {
+    handle.join().unwrap();
+  }
+  let content = std::fs::read_to_string(dir.join("entries.log")).unwrap();
+  for writer in 0..WRITERS {
+    for post in 0..POSTS_PER_WRITER {
+      let message = format!("writer {} post {}\n", writer, post);
+      assert!(content.contains(message.as_str()), "lost {}", message);
+    }
+  }
+  assert_eq!(content.lines().count(), WRITERS * POSTS_PER_WRITER);
+  // posts within the same millisecond still get an ins of their own
+  let mut ins_list: Vec<&str> = content
+    .lines()
+    .map(|line| {
+      line
+        .split("[ins:")
+        .nth(1)
+        .unwrap()
+        .split(' ')
+        .next()
+        .unwrap()
+    })
+    .collect();
+  ins_list.sort();
+  ins_list.dedup();
+  assert_eq!(ins_list.len(), WRITERS * POSTS_PER_WRITER);
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use common::{entrylogger, journal_dir};
+
+#[test]
+fn failed_post_exits_with_an_error() {
+  let dir = journal_dir("exit-status");
+  let output = entrylogger(
+    &dir,
+    &[
+      "-p",
+      "--date",
+      "today",
+      "--tag",
+      "fit",
+      "--message",
+      "run",
+      "--no-input",
+    ],
+  );
+  assert_eq!(output.status.code(), Some(1));
+  assert!(output.stdout.is_empty());
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert!(stderr.contains("Missing --time"), "{}", stderr);
+
+  let output = entrylogger(&dir, &["list"]);
+  assert!(output.status.success());
+  assert!(output.stdout.is_empty());
+}